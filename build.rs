@@ -1,7 +1,34 @@
-use std::process::Command;
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 fn main() {
     embuild::espidf::sysenv::output();
+
+    // Reported by GET /version, to tell which firmware a board runs
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let built_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={built_at}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    // Firmware-only builds don't need Node at all
+    if std::env::var_os("CARGO_FEATURE_EMBEDDED_UI").is_none() {
+        return;
+    }
+
     // Make sure we re-run build.rs if anything in web-ui/ changes
     println!("cargo:rerun-if-changed=web-ui/package.json");
     println!("cargo:rerun-if-changed=web-ui/package-lock.json");
@@ -0,0 +1,34 @@
+/// Something that can play the PCM clips from `assets`.
+/// Kept free of ESP dependencies so the host simulation can provide its own.
+pub trait AudioSink: Send + Sync {
+    /// Start playing `clip`, cutting off whatever was playing
+    fn play(&self, clip: &'static [u8]);
+
+    /// Cut off whatever is playing
+    fn stop(&self);
+
+    /// Whether there's something on the other end to hear it
+    fn is_connected(&self) -> bool;
+
+    /// Whether the sink's machinery (tasks, buffers) is up
+    fn is_healthy(&self) -> bool {
+        true
+    }
+
+    /// Pause or resume output for low-power mode
+    fn set_awake(&self, _awake: bool) {}
+}
+
+/// Sink that drops everything, for builds without any audio output
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn play(&self, _clip: &'static [u8]) {}
+
+    fn stop(&self) {}
+
+    fn is_connected(&self) -> bool {
+        false
+    }
+}
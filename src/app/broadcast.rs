@@ -0,0 +1,81 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+
+use serde::Serialize;
+
+use super::{AppState, LiveState};
+
+/// Updates a subscriber may fall behind by before it's considered stuck and dropped
+const SUBSCRIBER_BACKLOG: usize = 8;
+
+/// A game state change, as pushed to subscribers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StateUpdate {
+    pub app_state: AppState,
+    #[serde(flatten)]
+    pub live: LiveState,
+}
+
+impl StateUpdate {
+    /// Whether this differs from `previous` enough to be worth sending.
+    /// Held time only counts once it crosses a whole second, anything finer is noise on the wire.
+    pub fn change_since(&self, previous: &StateUpdate) -> Option<Change> {
+        if self.app_state != previous.app_state
+            || self.live.active != previous.live.active
+            || self.live.owner != previous.live.owner
+        {
+            return Some(Change::Lifecycle);
+        }
+
+        let (scores, previous) = (&self.live.scores, &previous.live.scores);
+        if scores.red().as_secs() != previous.red().as_secs()
+            || scores.blue().as_secs() != previous.blue().as_secs()
+        {
+            return Some(Change::Score);
+        }
+
+        None
+    }
+}
+
+/// What kind of change a `StateUpdate` carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Game started or ended, or the point changed hands
+    Lifecycle,
+    /// A team's held time ticked over another second
+    Score,
+}
+
+/// Fans state updates out to everything that wants to react to them (WS, LEDs, ...),
+/// so none of them has to poll the app.
+///
+/// Subscribers that hung up or stopped draining their queue are dropped on the next publish,
+/// a stuck consumer never holds up the game loop.
+#[derive(Debug, Default)]
+pub struct StateBroadcaster {
+    subscribers: Vec<SyncSender<StateUpdate>>,
+}
+
+impl StateBroadcaster {
+    pub fn subscribe(&mut self) -> Receiver<StateUpdate> {
+        let (tx, rx) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+        self.subscribers.push(tx);
+        rx
+    }
+
+    pub fn publish(&mut self, update: StateUpdate) {
+        self.subscribers
+            .retain(|subscriber| match subscriber.try_send(update) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::warn!("Dropping a state subscriber that fell behind");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+}
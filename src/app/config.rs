@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Where the sounds come out of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioOutput {
+    /// A2DP to a paired Bluetooth speaker
+    Bluetooth,
+    /// Wired amplifier on the I2S pins
+    I2s,
+}
+
+/// What holding both team buttons down does, for the referee without a phone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    /// End the game, whoever held the point longest wins
+    EndGame,
+    /// Drop the game without a winner
+    Reset,
+}
+
+/// Part a board plays when two run the same field, picks the UI bundle it serves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardRole {
+    /// Full referee controls
+    #[default]
+    Master,
+    /// Minimal score display
+    Slave,
+}
+
+impl BoardRole {
+    /// Subdirectory of the bundled UI with this role's front-end
+    pub fn ui_dir(self) -> &'static str {
+        match self {
+            BoardRole::Master => "master",
+            BoardRole::Slave => "slave",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// Bluetooth unless built with the `i2s-audio` feature
+    pub audio_output: AudioOutput,
+    /// Replaced at boot by the one saved with `AppClient::set_role`, if any
+    pub role: BoardRole,
+    /// How long the device may sit in `AppState::Idle` without any activity
+    /// before dropping into low-power mode. `None` disables auto-sleep.
+    pub idle_timeout: Option<Duration>,
+    /// Minimum gap between two score updates to subscribers. Captures and game start/end
+    /// always go out straight away.
+    pub broadcast_interval: Duration,
+    /// How long both buttons have to be held for `combo_action`
+    pub combo_hold: Duration,
+    pub combo_action: AdminAction,
+    /// Both teams have to press their button once while armed before a game can start,
+    /// see `ReadyState`
+    pub roll_call: bool,
+    /// Play `attract_interval` apart while idle, to draw players to the point
+    pub attract_mode: bool,
+    pub attract_interval: Duration,
+    /// Game loop cadence during a game, bounds the lag from a tick to the capture sound
+    pub game_loop_interval: Duration,
+    /// Game loop cadence outside games. Requests over the bus are answered straight away
+    /// regardless, this only slows down the background work.
+    pub idle_loop_interval: Duration,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            audio_output: if cfg!(feature = "i2s-audio") {
+                AudioOutput::I2s
+            } else {
+                AudioOutput::Bluetooth
+            },
+            role: BoardRole::default(),
+            idle_timeout: Some(Duration::from_secs(5 * 60)),
+            broadcast_interval: Duration::from_millis(250),
+            combo_hold: Duration::from_secs(2),
+            combo_action: AdminAction::EndGame,
+            roll_call: false,
+            attract_mode: false,
+            attract_interval: Duration::from_secs(3 * 60),
+            game_loop_interval: Duration::from_millis(20),
+            idle_loop_interval: Duration::from_millis(200),
+        }
+    }
+}
@@ -1,37 +1,546 @@
-use std::time::{Duration, Instant};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-#[derive(Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Team {
     Red,
     Blue,
 }
 
+impl Team {
+    /// The team on the other side
+    pub fn other(self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+}
+
+impl FromStr for Team {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("red") {
+            Ok(Team::Red)
+        } else if s.eq_ignore_ascii_case("blue") {
+            Ok(Team::Blue)
+        } else {
+            Err(anyhow::anyhow!("Unknown team '{s}', expected red or blue"))
+        }
+    }
+}
+
+/// Same leniency as `FromStr`, and a message that says what was wrong for anything else
+impl<'de> Deserialize<'de> for Team {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// What a button press did to the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressOutcome {
+    /// No game running or still in warmup, nothing happened
+    Ignored,
+    /// The team already held the point
+    AlreadyOwned,
+    /// Ownership moved to the team
+    Captured(Team),
+    /// Too soon after the last capture, nothing happened
+    Locked,
+}
+
+/// How a game ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "outcome", content = "team")]
+pub enum GameResult {
+    Won(Team),
+    /// Decided on held time with both teams exactly level
+    Draw,
+    /// Called off by the referee without a winner
+    NoWinner,
+}
+
+impl GameResult {
+    pub fn winner(&self) -> Option<Team> {
+        match self {
+            GameResult::Won(team) => Some(*team),
+            GameResult::Draw | GameResult::NoWinner => None,
+        }
+    }
+}
+
+/// Rules of a game, fixed while it's running
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GameConfig {
+    /// Held time a team needs to win
+    #[serde(rename = "time_to_win_ms", with = "millis")]
+    pub time_to_win: Duration,
+    /// Red's own target for attack/defend games, `null` uses `time_to_win`
+    #[serde(rename = "time_to_win_red_ms", with = "opt_millis", default)]
+    pub time_to_win_red: Option<Duration>,
+    /// Blue's own target, `null` uses `time_to_win`
+    #[serde(rename = "time_to_win_blue_ms", with = "opt_millis", default)]
+    pub time_to_win_blue: Option<Duration>,
+    /// After the point flips, presses are ignored for this long. Zero disables it.
+    #[serde(rename = "capture_lockout_ms", with = "millis", default)]
+    pub capture_lockout: Duration,
+    /// A press only counts once the button was held down this long, so a drive-by tap can't
+    /// flip the point. Zero counts every press.
+    #[serde(rename = "min_hold_ms", with = "millis", default)]
+    pub min_hold: Duration,
+    /// Held time red starts the game with, to even out uneven teams
+    #[serde(rename = "handicap_red_ms", with = "millis", default)]
+    pub handicap_red: Duration,
+    /// Held time blue starts the game with
+    #[serde(rename = "handicap_blue_ms", with = "millis", default)]
+    pub handicap_blue: Duration,
+    /// Countdown after the start before presses count
+    #[serde(rename = "warmup_ms", with = "millis", default)]
+    pub warmup: Duration,
+    /// Timed mode: the game ends after this long (warmup included) and the leader wins,
+    /// unless someone reached `time_to_win` first. `None` plays to `time_to_win` only.
+    #[serde(rename = "match_length_ms", with = "opt_millis", default)]
+    pub match_length: Option<Duration>,
+    /// Safety cap so a forgotten game doesn't run forever, the leader wins when it's hit.
+    /// `null` disables it.
+    #[serde(
+        rename = "max_duration_ms",
+        with = "opt_millis",
+        default = "default_max_duration"
+    )]
+    pub max_duration: Option<Duration>,
+    /// How fast red's held time counts, below 1 for an attacking side in attack/defend games
+    #[serde(default = "default_rate")]
+    pub rate_red: f32,
+    /// How fast blue's held time counts
+    #[serde(default = "default_rate")]
+    pub rate_blue: f32,
+    /// Fraction of its target at which a team gets a warning cue, e.g. 0.8 for 80%.
+    /// `null` for none.
+    #[serde(default)]
+    pub warning_threshold: Option<f32>,
+    /// Keep count of the time nobody held the point, mostly the stretch before the first
+    /// capture, for rulesets that count it against both teams. It goes to neither.
+    #[serde(default)]
+    pub start_neutral_penalty: bool,
+}
+
+/// Longest `time_to_win` accepted, a day of play is past any real game
+pub const MAX_TIME_TO_WIN: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest `min_hold` accepted, past that a team can't take the point in a firefight
+pub const MAX_MIN_HOLD: Duration = Duration::from_secs(10);
+
+/// A `time_to_win` no game can be played to
+fn target_out_of_range(target: Duration) -> bool {
+    target.is_zero() || target > MAX_TIME_TO_WIN
+}
+
+/// A `GameConfig` field with a value it can't take, named as in the JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: &'static str,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// Every field `GameConfig::validate` turned down, for reporting them all at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<FieldError>);
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Generous enough to never cut a real game short
+pub const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+
+fn default_max_duration() -> Option<Duration> {
+    Some(DEFAULT_MAX_DURATION)
+}
+
+fn default_rate() -> f32 {
+    1.0
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            time_to_win: Duration::from_secs(10),
+            time_to_win_red: None,
+            time_to_win_blue: None,
+            capture_lockout: Duration::ZERO,
+            min_hold: Duration::ZERO,
+            handicap_red: Duration::ZERO,
+            handicap_blue: Duration::ZERO,
+            warmup: Duration::ZERO,
+            match_length: None,
+            max_duration: default_max_duration(),
+            rate_red: default_rate(),
+            rate_blue: default_rate(),
+            warning_threshold: None,
+            start_neutral_penalty: false,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Everything that keeps these settings from making a playable game, empty if nothing.
+    /// Unlike `clamped` nothing is adjusted.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = vec![];
+        let mut fail = |field, message| errors.push(FieldError { field, message });
+
+        if target_out_of_range(self.time_to_win) {
+            fail("time_to_win_ms", "Must be above zero and at most a day");
+        }
+        if self.time_to_win_red.is_some_and(target_out_of_range) {
+            fail("time_to_win_red_ms", "Must be above zero and at most a day");
+        }
+        if self.time_to_win_blue.is_some_and(target_out_of_range) {
+            fail("time_to_win_blue_ms", "Must be above zero and at most a day");
+        }
+        if self.min_hold > MAX_MIN_HOLD {
+            fail("min_hold_ms", "Must be at most 10s");
+        }
+        if self.handicap_red >= self.target(Team::Red) {
+            fail("handicap_red_ms", "Must be below red's time to win");
+        }
+        if self.handicap_blue >= self.target(Team::Blue) {
+            fail("handicap_blue_ms", "Must be below blue's time to win");
+        }
+        if self.match_length.is_some_and(|length| length <= self.warmup) {
+            fail("match_length_ms", "Must be longer than warmup_ms");
+        }
+        if self.max_duration.is_some_and(|max| max <= self.warmup) {
+            fail("max_duration_ms", "Must be longer than warmup_ms");
+        }
+        if !(self.rate_red.is_finite() && self.rate_red > 0.0) {
+            fail("rate_red", "Must be above zero");
+        }
+        if !(self.rate_blue.is_finite() && self.rate_blue > 0.0) {
+            fail("rate_blue", "Must be above zero");
+        }
+        if self.warning_threshold.is_some_and(|t| !(t > 0.0 && t < 1.0)) {
+            fail("warning_threshold", "Must be between 0 and 1");
+        }
+
+        errors
+    }
+
+    /// Keep head starts short of the team's target, a team can't begin the game having won it.
+    /// Rates that would stall or break the clock go back to 1, a warning threshold outside
+    /// 0 to 1 is dropped, `min_hold` is capped at `MAX_MIN_HOLD`.
+    pub fn clamped(self) -> Self {
+        let max_handicap = |team| self.target(team).saturating_sub(Duration::from_millis(1));
+        let rate = |rate: f32| {
+            if rate.is_finite() && rate > 0.0 {
+                rate
+            } else {
+                default_rate()
+            }
+        };
+        Self {
+            handicap_red: self.handicap_red.min(max_handicap(Team::Red)),
+            handicap_blue: self.handicap_blue.min(max_handicap(Team::Blue)),
+            rate_red: rate(self.rate_red),
+            rate_blue: rate(self.rate_blue),
+            warning_threshold: self.warning_threshold.filter(|t| *t > 0.0 && *t < 1.0),
+            min_hold: self.min_hold.min(MAX_MIN_HOLD),
+            ..self
+        }
+    }
+
+    /// Held time `team` needs to win
+    pub fn target(&self, team: Team) -> Duration {
+        let own = match team {
+            Team::Red => self.time_to_win_red,
+            Team::Blue => self.time_to_win_blue,
+        };
+        own.unwrap_or(self.time_to_win)
+    }
+
+    /// Held time at which `team` gets its warning cue, `None` without a threshold
+    pub fn warning_at(&self, team: Team) -> Option<Duration> {
+        self.warning_threshold
+            .map(|threshold| self.target(team).mul_f32(threshold))
+    }
+
+    /// Held time multiplier of `team`
+    pub fn rate(&self, team: Team) -> f32 {
+        match team {
+            Team::Red => self.rate_red,
+            Team::Blue => self.rate_blue,
+        }
+    }
+}
+
+/// Turns a button's level into presses for `GameConfig::min_hold`. A hold makes at most one
+/// press, either as soon as it lasted `min_hold` or, if nobody looked in time, on its release.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HoldGate {
+    down_since: Option<Instant>,
+    fired: bool,
+}
+
+impl HoldGate {
+    /// The button went down (`held`) or came back up. `true` when the release ends a hold of
+    /// at least `min_hold` that `poll` hadn't caught yet.
+    pub fn set_held(&mut self, held: bool, now: Instant, min_hold: Duration) -> bool {
+        if held {
+            self.down_since.get_or_insert(now);
+            return false;
+        }
+        let fire = match self.down_since.take() {
+            Some(since) => !self.fired && now.duration_since(since) >= min_hold,
+            None => false,
+        };
+        self.fired = false;
+        fire
+    }
+
+    /// `true` once per hold, the first time it's called with the button down for `min_hold`
+    pub fn poll(&mut self, now: Instant, min_hold: Duration) -> bool {
+        let Some(since) = self.down_since else {
+            return false;
+        };
+        if self.fired || now.duration_since(since) < min_hold {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
+
+/// How long arming lets a game be started for
+pub const ARM_WINDOW: Duration = Duration::from_secs(30);
+
+/// The step before starting a game, against starting one by accident: a game can only be
+/// started within `ARM_WINDOW` of arming, and starting it disarms
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Arming {
+    until: Option<Instant>,
+}
+
+impl Arming {
+    pub fn arm_at(&mut self, now: Instant) {
+        self.until = Some(now + ARM_WINDOW);
+    }
+
+    pub fn disarm(&mut self) {
+        self.until = None;
+    }
+
+    pub fn is_armed_at(&self, now: Instant) -> bool {
+        self.until.is_some_and(|until| now < until)
+    }
+}
+
+/// Length of the closing phase of a timed game
+pub const FINAL_PHASE: Duration = Duration::from_secs(60);
+
+/// How much faster held time counts during the final phase
+pub const FINAL_PHASE_RATE: u32 = 2;
+
+/// Where a running game is in its schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Counting down to the start, presses are ignored
+    Warmup,
+    /// Regular play, the only phase without warmup or timed mode
+    Play,
+    /// Last `FINAL_PHASE` of a timed game, held time counts `FINAL_PHASE_RATE` times over
+    FinalMinute,
+}
+
+/// What it takes to carry a running game over a reboot. Durations rather than `Instant`s,
+/// those mean nothing once the clock restarted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(rename = "red_ms", with = "millis")]
+    pub red: Duration,
+    #[serde(rename = "blue_ms", with = "millis")]
+    pub blue: Duration,
+    #[serde(rename = "neutral_ms", with = "millis", default)]
+    pub neutral: Duration,
+    pub captures_red: u32,
+    pub captures_blue: u32,
+    pub owner: Option<Team>,
+    #[serde(rename = "elapsed_ms", with = "millis")]
+    pub elapsed: Duration,
+    pub config: GameConfig,
+}
+
+/// A new `time_to_win` would be at or below what a team already held, ending the game on the spot
 #[derive(Debug, Clone, Copy)]
+pub struct BelowHeldTime {
+    pub held: Duration,
+}
+
+impl std::fmt::Display for BelowHeldTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A team already held {}ms, force it to end the game now",
+            self.held.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for BelowHeldTime {}
+
+/// Ownership changes kept per game, past that the oldest go
+pub const MAX_OWNER_SPANS: usize = 128;
+
+/// A stretch of the game one team held the point, times from the game start
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OwnerSpan {
+    pub team: Team,
+    #[serde(rename = "start_ms", with = "millis")]
+    pub start: Duration,
+    /// `None` while the team still holds the point
+    #[serde(rename = "end_ms", with = "opt_millis")]
+    pub end: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
 pub struct GameState {
     active: bool,
     current_team: Option<Team>,
     last_tick: Option<Instant>,
     team_red_time: Duration,
     team_blue_time: Duration,
-    time_to_win: Duration,
+    /// Unowned time, see `GameConfig::start_neutral_penalty`
+    neutral_time: Duration,
+    /// The team got its `GameConfig::warning_threshold` cue this game
+    warned_red: bool,
+    warned_blue: bool,
+    captures_red: u32,
+    captures_blue: u32,
+    last_capture: Option<Instant>,
+    /// Wall clock start of the running game
+    game_started_at: Option<Instant>,
+    /// Who held the point when, oldest first. Kept after the game for a look back.
+    owner_history: Vec<OwnerSpan>,
+    config: GameConfig,
 }
 
 impl Default for GameState {
     fn default() -> Self {
-        GameState::new(Duration::from_secs(10))
+        GameState::with_config(GameConfig::default())
     }
 }
 
 impl GameState {
     pub fn new(time_to_win: Duration) -> Self {
+        Self::with_config(GameConfig {
+            time_to_win,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_config(config: GameConfig) -> Self {
         Self {
             active: false,
             current_team: None,
             last_tick: None,
             team_red_time: Duration::ZERO,
             team_blue_time: Duration::ZERO,
+            neutral_time: Duration::ZERO,
+            warned_red: false,
+            warned_blue: false,
+            captures_red: 0,
+            captures_blue: 0,
+            last_capture: None,
+            game_started_at: None,
+            owner_history: vec![],
+            config: config.clamped(),
+        }
+    }
+
+    pub fn config(&self) -> GameConfig {
+        self.config
+    }
+
+    /// Change the rules, only between games
+    pub fn set_config(&mut self, config: GameConfig) -> anyhow::Result<()> {
+        if self.active {
+            return Err(anyhow::anyhow!("Can't change the rules mid-game"));
+        }
+        self.config = config.clamped();
+        Ok(())
+    }
+
+    /// Change the shared target, also mid-game to correct a wrong setting. Per-team targets
+    /// stay as they are. Fails with a `FieldError` for a target `GameConfig::validate` turns
+    /// down, `force` or not. Going at or below the time a team already held would hand them
+    /// the win, that fails with `BelowHeldTime` unless `force`. Check `winner()` afterwards.
+    pub fn adjust_time_to_win(&mut self, time_to_win: Duration, force: bool) -> anyhow::Result<()> {
+        self.adjust_time_to_win_at(time_to_win, force, Instant::now())
+    }
+
+    /// `adjust_time_to_win` with an explicit time source
+    pub fn adjust_time_to_win_at(
+        &mut self,
+        time_to_win: Duration,
+        force: bool,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        if target_out_of_range(time_to_win) {
+            return Err(FieldError {
+                field: "time_to_win_ms",
+                message: "Must be above zero and at most a day",
+            }
+            .into());
+        }
+        // Compare against the held time up to now, not the last tick
+        self.accrue(now);
+
+        let config = GameConfig {
             time_to_win,
+            ..self.config
+        };
+        let past_target = [Team::Red, Team::Blue]
+            .into_iter()
+            .find(|&team| config.target(team) <= self.held(team));
+        if let Some(team) = past_target {
+            if self.active && !force {
+                return Err(BelowHeldTime {
+                    held: self.held(team),
+                }
+                .into());
+            }
         }
+
+        self.config = config.clamped();
+        log::info!(target: "game", "Time to win set to {}ms", time_to_win.as_millis());
+        Ok(())
     }
 
     pub fn active(&self) -> bool {
@@ -40,76 +549,425 @@ impl GameState {
 
     /// Start or restart the game
     pub fn start(&mut self) {
+        self.start_at(Instant::now());
+    }
+
+    /// `start` with an explicit time source
+    pub fn start_at(&mut self, now: Instant) {
         self.active = true;
         self.current_team = None;
-        self.last_tick = Some(Instant::now());
-        self.team_red_time = Duration::ZERO;
-        self.team_blue_time = Duration::ZERO;
-        log::info!("Game started");
+        self.last_tick = Some(now);
+        // Handicaps are a head start, they count towards `time_to_win` like held time does
+        self.team_red_time = self.config.handicap_red;
+        self.team_blue_time = self.config.handicap_blue;
+        self.neutral_time = Duration::ZERO;
+        // A head start past the threshold isn't news, no cue for it
+        self.warned_red = self.past_warning(Team::Red);
+        self.warned_blue = self.past_warning(Team::Blue);
+        self.captures_red = 0;
+        self.captures_blue = 0;
+        self.last_capture = None;
+        self.game_started_at = Some(now);
+        self.owner_history.clear();
+        log::info!(target: "game", "Game started");
+    }
+
+    /// Snapshot of a running game to resume from later, `None` between games
+    pub fn checkpoint_at(&mut self, now: Instant) -> Option<Checkpoint> {
+        if !self.active {
+            return None;
+        }
+        self.accrue(now);
+
+        Some(Checkpoint {
+            red: self.team_red_time,
+            blue: self.team_blue_time,
+            neutral: self.neutral_time,
+            captures_red: self.captures_red,
+            captures_blue: self.captures_blue,
+            owner: self.current_team,
+            elapsed: self.elapsed_at(now),
+            config: self.config,
+        })
+    }
+
+    /// Pick a checkpointed game back up, counting continues from `now`.
+    /// The time the device was down counts for nobody, and no capture lockout carries over.
+    pub fn resume_at(&mut self, checkpoint: Checkpoint, now: Instant) {
+        self.active = true;
+        self.config = checkpoint.config.clamped();
+        self.current_team = checkpoint.owner;
+        self.last_tick = Some(now);
+        self.team_red_time = checkpoint.red;
+        self.team_blue_time = checkpoint.blue;
+        self.neutral_time = checkpoint.neutral;
+        // Whoever was past the threshold got their cue before the reboot
+        self.warned_red = self.past_warning(Team::Red);
+        self.warned_blue = self.past_warning(Team::Blue);
+        self.captures_red = checkpoint.captures_red;
+        self.captures_blue = checkpoint.captures_blue;
+        self.last_capture = None;
+        self.game_started_at = Some(now.checked_sub(checkpoint.elapsed).unwrap_or(now));
+        // Only the current holder is known, the timeline before the reboot is gone
+        self.owner_history.clear();
+        if let Some(team) = checkpoint.owner {
+            self.open_span(team, now);
+        }
+        log::info!(target: "game", "Game resumed");
     }
 
     /// Stop the game (no more accumulation)
     pub fn stop(&mut self) {
-        self.tick();
+        self.stop_at(Instant::now());
+    }
+
+    /// `stop` with an explicit time source
+    pub fn stop_at(&mut self, now: Instant) {
+        self.accrue(now);
+        self.close_span(now);
         self.active = false;
         self.current_team = None;
         self.last_tick = None;
-        log::info!("Game stopped");
+        self.game_started_at = None;
+        log::info!(target: "game", "Game stopped");
     }
 
     /// Handle a button press
-    pub fn button_press(&mut self, team: Team) {
-        if !self.active {
-            return;
+    pub fn button_press(&mut self, team: Team) -> PressOutcome {
+        self.button_press_at(team, Instant::now())
+    }
+
+    /// `button_press` with an explicit time source
+    pub fn button_press_at(&mut self, team: Team, now: Instant) -> PressOutcome {
+        if !self.active || self.phase_at(now) == Some(Phase::Warmup) {
+            return PressOutcome::Ignored;
+        }
+
+        if self.lockout_remaining_at(now) > Duration::ZERO {
+            return PressOutcome::Locked;
         }
 
         // First, account for time so far
-        self.tick();
+        self.accrue(now);
+
+        log::info!(target: "game", "{team:#?} pressed the button");
+
+        if self.current_team == Some(team) {
+            return PressOutcome::AlreadyOwned;
+        }
 
         // Switch ownership
+        self.close_span(now);
+        self.open_span(team, now);
         self.current_team = Some(team);
+        self.last_capture = Some(now);
+        match team {
+            Team::Red => self.captures_red += 1,
+            Team::Blue => self.captures_blue += 1,
+        }
+
+        PressOutcome::Captured(team)
+    }
+
+    /// Admin override of who owns the point, for when a button fails. Doesn't count as a
+    /// capture nor start a lockout. `false` if no game is running.
+    pub fn set_owner(&mut self, team: Option<Team>) -> bool {
+        self.set_owner_at(team, Instant::now())
+    }
+
+    /// `set_owner` with an explicit time source
+    pub fn set_owner_at(&mut self, team: Option<Team>, now: Instant) -> bool {
+        if !self.active {
+            return false;
+        }
+        // The time so far belongs to the previous owner
+        self.accrue(now);
+        if self.current_team != team {
+            self.close_span(now);
+            if let Some(team) = team {
+                self.open_span(team, now);
+            }
+        }
+        self.current_team = team;
+        true
+    }
+
+    /// Who held the point when, also after the game ended until the next one starts
+    pub fn owner_history(&self) -> &[OwnerSpan] {
+        &self.owner_history
+    }
+
+    fn open_span(&mut self, team: Team, now: Instant) {
+        if self.owner_history.len() >= MAX_OWNER_SPANS {
+            self.owner_history.remove(0);
+        }
+        self.owner_history.push(OwnerSpan {
+            team,
+            start: self.elapsed_at(now),
+            end: None,
+        });
+    }
+
+    fn close_span(&mut self, now: Instant) {
+        let end = self.elapsed_at(now);
+        if let Some(span) = self.owner_history.last_mut().filter(|span| span.end.is_none()) {
+            span.end = Some(end);
+        }
+    }
 
-        log::info!("{team:#?} pressed the button");
+    /// Referee penalty or bonus: add `delta_secs` to the held time of `team`, or take it off
+    /// when negative, never below zero. `false` if no game is running.
+    /// Check `winner()` afterwards.
+    pub fn adjust_time(&mut self, team: Team, delta_secs: i32) -> bool {
+        self.adjust_time_at(team, delta_secs, Instant::now())
     }
 
-    /// Call this periodically (e.g. every 50–100 ms)
-    pub fn tick(&mut self) {
+    /// `adjust_time` with an explicit time source
+    pub fn adjust_time_at(&mut self, team: Team, delta_secs: i32, now: Instant) -> bool {
+        if !self.active {
+            return false;
+        }
+        // Settle the time so far first, the adjustment goes on top of it
+        self.accrue(now);
+
+        let delta = Duration::from_secs(delta_secs.unsigned_abs().into());
+        let held = match team {
+            Team::Red => &mut self.team_red_time,
+            Team::Blue => &mut self.team_blue_time,
+        };
+        *held = if delta_secs < 0 {
+            held.saturating_sub(delta)
+        } else {
+            held.saturating_add(delta)
+        };
+        true
+    }
+
+    /// Call this periodically (e.g. every 50–100 ms). Returns the team that just went past
+    /// its warning threshold, once per team and game.
+    pub fn tick(&mut self) -> Option<Team> {
+        self.tick_at(Instant::now())
+    }
+
+    /// `tick` with an explicit time source
+    pub fn tick_at(&mut self, now: Instant) -> Option<Team> {
+        self.accrue(now);
+
+        let team = [Team::Red, Team::Blue]
+            .into_iter()
+            .find(|&team| !self.warned(team) && self.past_warning(team))?;
+        match team {
+            Team::Red => self.warned_red = true,
+            Team::Blue => self.warned_blue = true,
+        }
+        Some(team)
+    }
+
+    /// `team` got its warning cue this game
+    pub fn warned(&self, team: Team) -> bool {
+        match team {
+            Team::Red => self.warned_red,
+            Team::Blue => self.warned_blue,
+        }
+    }
+
+    fn past_warning(&self, team: Team) -> bool {
+        self.config
+            .warning_at(team)
+            .is_some_and(|warning| self.held(team) >= warning)
+    }
+
+    /// Count the held time up to `now`, all the accounting lives here
+    fn accrue(&mut self, now: Instant) {
         if !self.active {
             return;
         }
 
-        let now = Instant::now();
         let Some(last) = self.last_tick else {
             self.last_tick = Some(now);
             return;
         };
 
-        let delta = now.duration_since(last);
+        let delta = self.counted_time(last, now);
 
-        if let Some(owner) = self.current_team {
-            match owner {
-                Team::Blue => self.team_blue_time += delta,
-                Team::Red => self.team_red_time += delta,
+        // Rates scale held time itself, so `time_to_win` and progress need no adjusting
+        match self.current_team {
+            Some(owner) => {
+                let scaled = delta.as_secs_f64() * f64::from(self.config.rate(owner));
+                let delta = Duration::try_from_secs_f64(scaled).unwrap_or(Duration::MAX);
+                // Nothing past twice the target means anything, however long a stuck game
+                // keeps ticking
+                let cap = self.config.target(owner).saturating_mul(2);
+                let held = match owner {
+                    Team::Blue => &mut self.team_blue_time,
+                    Team::Red => &mut self.team_red_time,
+                };
+                *held = held.saturating_add(delta).min(cap);
+            }
+            None if self.config.start_neutral_penalty => {
+                self.neutral_time = self.neutral_time.saturating_add(delta);
             }
+            None => {}
         }
 
         self.last_tick = Some(now);
     }
 
-    /// Check if someone won
+    /// Held time earned between `from` and `to`: nothing during warmup,
+    /// `FINAL_PHASE_RATE` times the wall clock in the final phase
+    fn counted_time(&self, from: Instant, to: Instant) -> Duration {
+        let Some(started) = self.game_started_at else {
+            return to.saturating_duration_since(from);
+        };
+
+        let play_start = started + self.config.warmup;
+        let final_start = self
+            .config
+            .match_length
+            .map(|length| (started + length.saturating_sub(FINAL_PHASE)).max(play_start));
+
+        let overlap = |start: Instant, end: Option<Instant>| {
+            let end = end.map_or(to, |end| end.min(to));
+            end.saturating_duration_since(from.max(start))
+        };
+
+        match final_start {
+            Some(final_start) => {
+                overlap(play_start, Some(final_start))
+                    .saturating_add(overlap(final_start, None).saturating_mul(FINAL_PHASE_RATE))
+            }
+            None => overlap(play_start, None),
+        }
+    }
+
+    /// Current phase, `None` between games
+    pub fn phase(&self) -> Option<Phase> {
+        self.phase_at(Instant::now())
+    }
+
+    /// `phase` with an explicit time source
+    pub fn phase_at(&self, now: Instant) -> Option<Phase> {
+        if !self.active {
+            return None;
+        }
+
+        let elapsed = self.elapsed_at(now);
+        if elapsed < self.config.warmup {
+            return Some(Phase::Warmup);
+        }
+        match self.config.match_length {
+            Some(length) if length.saturating_sub(elapsed) <= FINAL_PHASE => {
+                Some(Phase::FinalMinute)
+            }
+            _ => Some(Phase::Play),
+        }
+    }
+
+    /// Timed mode and the match length ran out
+    pub fn time_up(&self) -> bool {
+        self.time_up_at(Instant::now())
+    }
+
+    /// `time_up` with an explicit time source
+    pub fn time_up_at(&self, now: Instant) -> bool {
+        self.active
+            && self
+                .config
+                .match_length
+                .is_some_and(|length| self.elapsed_at(now) >= length)
+    }
+
+    /// The game ran into `max_duration`
+    pub fn past_max_duration(&self) -> bool {
+        self.past_max_duration_at(Instant::now())
+    }
+
+    /// `past_max_duration` with an explicit time source
+    pub fn past_max_duration_at(&self, now: Instant) -> bool {
+        self.active
+            && self
+                .config
+                .max_duration
+                .is_some_and(|max| self.elapsed_at(now) >= max)
+    }
+
+    /// Check if someone won, each team against its own target
     pub fn winner(&self) -> Option<Team> {
-        if self.team_blue_time >= self.time_to_win {
-            Some(Team::Red)
-        } else if self.team_red_time >= self.time_to_win {
-            Some(Team::Blue)
-        } else {
-            None
+        [Team::Blue, Team::Red]
+            .into_iter()
+            .find(|&team| self.held(team) >= self.config.target(team))
+    }
+
+    /// Held time of `team` so far, head start included
+    fn held(&self, team: Team) -> Duration {
+        match team {
+            Team::Red => self.team_red_time,
+            Team::Blue => self.team_blue_time,
+        }
+    }
+
+    /// The result if the game were decided on held time now: the leader wins,
+    /// a dead heat is a draw. Unlike `winner` this doesn't wait for `time_to_win`.
+    pub fn decide(&self) -> GameResult {
+        match self.leader() {
+            Some(team) => GameResult::Won(team),
+            None => GameResult::Draw,
+        }
+    }
+
+    /// Who held the point longest so far, `None` on a tie
+    pub fn leader(&self) -> Option<Team> {
+        match self.team_red_time.cmp(&self.team_blue_time) {
+            std::cmp::Ordering::Greater => Some(Team::Red),
+            std::cmp::Ordering::Less => Some(Team::Blue),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// Wall clock time since the game started, whoever held the point. Zero between games.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(Instant::now())
+    }
+
+    /// `elapsed` with an explicit time source
+    pub fn elapsed_at(&self, now: Instant) -> Duration {
+        self.game_started_at
+            .map_or(Duration::ZERO, |started| now.saturating_duration_since(started))
+    }
+
+    /// Held time `team` still needs to win. With a `rate` other than 1 the wall clock time
+    /// it takes differs, `remaining / rate`.
+    pub fn remaining(&self, team: Team) -> Duration {
+        self.config.target(team).saturating_sub(self.held(team))
+    }
+
+    /// How much longer presses are ignored after the last capture
+    pub fn lockout_remaining_at(&self, now: Instant) -> Duration {
+        match self.last_capture {
+            Some(last) => self
+                .config
+                .capture_lockout
+                .saturating_sub(now.saturating_duration_since(last)),
+            None => Duration::ZERO,
         }
     }
 
     /// Expose current scores (for UI / WS)
     pub fn scores(&self) -> Scores {
-        Scores { red: self.team_red_time, blue: self.team_blue_time }
+        self.scores_at(Instant::now())
+    }
+
+    /// `scores` with an explicit time source
+    pub fn scores_at(&self, now: Instant) -> Scores {
+        Scores {
+            red: self.team_red_time,
+            blue: self.team_blue_time,
+            neutral: self.neutral_time,
+            captures_red: self.captures_red,
+            captures_blue: self.captures_blue,
+            lockout_remaining: self.lockout_remaining_at(now),
+            elapsed: self.elapsed_at(now),
+        }
     }
 
     /// Who currently owns the point
@@ -118,7 +976,512 @@ impl GameState {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct Scores {
+    /// Held time so far, head start included
+    #[serde(rename = "red_ms", with = "millis")]
     red: Duration,
-    blue: Duration
-}
\ No newline at end of file
+    #[serde(rename = "blue_ms", with = "millis")]
+    blue: Duration,
+    /// Time nobody held the point, only counted with `start_neutral_penalty`
+    #[serde(rename = "neutral_ms", with = "millis")]
+    neutral: Duration,
+    /// Times each team took the point over
+    captures_red: u32,
+    captures_blue: u32,
+    /// Non-zero while presses are locked out after a capture
+    #[serde(rename = "lockout_remaining_ms", with = "millis")]
+    lockout_remaining: Duration,
+    /// Game clock, unlike the held times it runs whether or not the point is owned
+    #[serde(rename = "elapsed_ms", with = "millis")]
+    elapsed: Duration,
+}
+
+impl Scores {
+    pub fn red(&self) -> Duration {
+        self.red
+    }
+
+    pub fn blue(&self) -> Duration {
+        self.blue
+    }
+
+    pub fn neutral(&self) -> Duration {
+        self.neutral
+    }
+
+    pub fn captures_red(&self) -> u32 {
+        self.captures_red
+    }
+
+    pub fn captures_blue(&self) -> u32 {
+        self.captures_blue
+    }
+
+    pub fn lockout_remaining(&self) -> Duration {
+        self.lockout_remaining
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// `millis` for an optional `Duration`, `null` for `None`
+mod opt_millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_some(&super::millis::to_u64(duration)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
+
+/// (De)serializes a `Duration` as whole milliseconds
+pub(crate) mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(to_u64(duration))
+    }
+
+    /// Whole milliseconds, pinned at `u64::MAX` rather than wrapping
+    pub fn to_u64(duration: &Duration) -> u64 {
+        u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    /// A game to `time_to_win` seconds, started at the returned instant
+    fn started(time_to_win: u64) -> (GameState, Instant) {
+        let mut game = GameState::new(secs(time_to_win));
+        let start = Instant::now();
+        game.start_at(start);
+        (game, start)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// A game under `config`, started at the returned instant
+    fn started_with(config: GameConfig) -> (GameState, Instant) {
+        let mut game = GameState::with_config(config);
+        let start = Instant::now();
+        game.start_at(start);
+        (game, start)
+    }
+
+    #[test]
+    fn tap_shorter_than_min_hold_is_no_press() {
+        let mut gate = HoldGate::default();
+        let start = Instant::now();
+        assert!(!gate.set_held(true, start, secs(1)));
+        assert!(!gate.poll(start + ms(300), secs(1)));
+        assert!(!gate.set_held(false, start + ms(400), secs(1)));
+        assert!(!gate.poll(start + secs(2), secs(1)));
+    }
+
+    #[test]
+    fn hold_past_min_hold_presses_once() {
+        let mut gate = HoldGate::default();
+        let start = Instant::now();
+        gate.set_held(true, start, secs(1));
+        assert!(!gate.poll(start + ms(500), secs(1)));
+        assert!(gate.poll(start + ms(1_100), secs(1)));
+        assert!(!gate.poll(start + ms(1_500), secs(1)));
+        // Already counted while down, letting go adds nothing
+        assert!(!gate.set_held(false, start + secs(2), secs(1)));
+    }
+
+    #[test]
+    fn hold_nobody_polled_presses_on_release() {
+        let mut gate = HoldGate::default();
+        let start = Instant::now();
+        gate.set_held(true, start, secs(1));
+        assert!(gate.set_held(false, start + ms(1_500), secs(1)));
+        assert!(!gate.poll(start + secs(2), secs(1)));
+    }
+
+    #[test]
+    fn each_hold_presses_on_its_own() {
+        let mut gate = HoldGate::default();
+        let start = Instant::now();
+        gate.set_held(true, start, secs(1));
+        assert!(gate.poll(start + secs(1), secs(1)));
+        gate.set_held(false, start + secs(2), secs(1));
+
+        gate.set_held(true, start + secs(3), secs(1));
+        assert!(!gate.poll(start + ms(3_500), secs(1)));
+        assert!(gate.poll(start + secs(4), secs(1)));
+    }
+
+    #[test]
+    fn repeated_down_keeps_the_first_edge() {
+        let mut gate = HoldGate::default();
+        let start = Instant::now();
+        gate.set_held(true, start, secs(1));
+        gate.set_held(true, start + ms(900), secs(1));
+        assert!(gate.poll(start + secs(1), secs(1)));
+    }
+
+    #[test]
+    fn blue_reaching_its_target_wins() {
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Blue, start);
+
+        game.tick_at(start + secs(9));
+        assert_eq!(game.winner(), None);
+        game.tick_at(start + secs(10));
+        assert_eq!(game.winner(), Some(Team::Blue));
+    }
+
+    #[test]
+    fn red_reaching_its_target_wins() {
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Red, start);
+
+        game.tick_at(start + secs(10));
+        assert_eq!(game.winner(), Some(Team::Red));
+    }
+
+    #[test]
+    fn each_team_wins_at_its_own_target() {
+        let config = GameConfig {
+            time_to_win_red: Some(secs(5)),
+            time_to_win_blue: Some(secs(15)),
+            ..Default::default()
+        };
+
+        let (mut game, start) = started_with(config);
+        game.button_press_at(Team::Red, start);
+        game.tick_at(start + ms(4_999));
+        assert_eq!(game.winner(), None);
+        game.tick_at(start + secs(5));
+        assert_eq!(game.winner(), Some(Team::Red));
+
+        // Red's target means nothing to blue
+        let (mut game, start) = started_with(config);
+        game.button_press_at(Team::Blue, start);
+        game.tick_at(start + secs(5));
+        assert_eq!(game.winner(), None);
+        game.tick_at(start + ms(14_999));
+        assert_eq!(game.winner(), None);
+        game.tick_at(start + secs(15));
+        assert_eq!(game.winner(), Some(Team::Blue));
+    }
+
+    #[test]
+    fn contested_point_splits_held_time() {
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Red, start);
+        game.tick_at(start + secs(2));
+        game.button_press_at(Team::Blue, start + secs(4));
+        game.tick_at(start + secs(5));
+        game.button_press_at(Team::Red, start + secs(7));
+        game.tick_at(start + secs(9));
+
+        let scores = game.scores_at(start + secs(9));
+        assert_eq!(scores.red(), secs(6));
+        assert_eq!(scores.blue(), secs(3));
+        assert_eq!(scores.captures_red(), 2);
+        assert_eq!(scores.captures_blue(), 1);
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.leader(), Some(Team::Red));
+    }
+
+    #[test]
+    fn pressing_for_the_owner_changes_nothing() {
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Red, start);
+        let outcome = game.button_press_at(Team::Red, start + secs(3));
+
+        assert_eq!(outcome, PressOutcome::AlreadyOwned);
+        assert_eq!(game.scores_at(start + secs(3)).red(), secs(3));
+        assert_eq!(game.scores_at(start + secs(3)).captures_red(), 1);
+    }
+
+    #[test]
+    fn held_time_accumulates_until_stop() {
+        let (mut game, start) = started(60);
+        game.button_press_at(Team::Red, start);
+        // Ticks in between don't count anything twice
+        game.tick_at(start + secs(3));
+        game.tick_at(start + secs(5));
+        game.stop_at(start + secs(6));
+        assert_eq!(game.scores().red(), secs(6));
+
+        // Nothing counts between games, presses included
+        game.tick_at(start + secs(20));
+        assert_eq!(game.button_press_at(Team::Blue, start + secs(21)), PressOutcome::Ignored);
+        assert_eq!(game.scores().red(), secs(6));
+        assert_eq!(game.scores().blue(), Duration::ZERO);
+    }
+
+    #[test]
+    fn starting_again_resets_held_time() {
+        let (mut game, start) = started(60);
+        game.button_press_at(Team::Red, start);
+        game.stop_at(start + secs(6));
+
+        let restart = start + secs(30);
+        game.start_at(restart);
+        assert_eq!(game.current_team(), None);
+        assert_eq!(game.scores_at(restart).red(), Duration::ZERO);
+
+        game.button_press_at(Team::Blue, restart);
+        game.stop_at(restart + secs(2));
+        assert_eq!(game.scores().red(), Duration::ZERO);
+        assert_eq!(game.scores().blue(), secs(2));
+    }
+
+    #[test]
+    fn adjusting_to_an_unplayable_target_is_refused() {
+        let mut idle = GameState::new(secs(10));
+        let now = Instant::now();
+        for target in [Duration::ZERO, MAX_TIME_TO_WIN + ms(1)] {
+            let err = idle.adjust_time_to_win_at(target, false, now).unwrap_err();
+            assert_eq!(err.downcast_ref::<FieldError>().unwrap().field, "time_to_win_ms");
+        }
+        assert_eq!(idle.config().time_to_win, secs(10));
+
+        // Not even `force` ends a running game this way
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Red, start);
+        let err = game.adjust_time_to_win_at(Duration::ZERO, true, start + secs(1)).unwrap_err();
+        assert!(err.is::<FieldError>());
+        assert!(game.active());
+        assert_eq!(game.config().time_to_win, secs(10));
+
+        assert!(idle.adjust_time_to_win_at(MAX_TIME_TO_WIN, false, now).is_ok());
+    }
+
+    #[test]
+    fn adjusting_below_held_time_takes_force() {
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Red, start);
+
+        let err = game.adjust_time_to_win_at(secs(4), false, start + secs(5)).unwrap_err();
+        assert_eq!(err.downcast_ref::<BelowHeldTime>().unwrap().held, secs(5));
+        assert_eq!(game.config().time_to_win, secs(10));
+        assert_eq!(game.winner(), None);
+
+        game.adjust_time_to_win_at(secs(4), true, start + secs(5)).unwrap();
+        assert_eq!(game.winner(), Some(Team::Red));
+    }
+
+    #[test]
+    fn adjusting_above_held_time_keeps_playing() {
+        let (mut game, start) = started(10);
+        game.button_press_at(Team::Red, start);
+
+        game.adjust_time_to_win_at(secs(20), false, start + secs(5)).unwrap();
+        game.tick_at(start + secs(19));
+        assert_eq!(game.winner(), None);
+        game.tick_at(start + secs(20));
+        assert_eq!(game.winner(), Some(Team::Red));
+    }
+
+    #[test]
+    fn dead_heat_is_a_draw() {
+        let (mut game, start) = started(60);
+        game.button_press_at(Team::Red, start);
+        game.button_press_at(Team::Blue, start + secs(3));
+        game.stop_at(start + secs(6));
+
+        assert_eq!(game.leader(), None);
+        assert_eq!(game.decide(), GameResult::Draw);
+    }
+
+    #[test]
+    fn longest_hold_decides_the_winner() {
+        let (mut game, start) = started(60);
+        game.button_press_at(Team::Red, start);
+        game.button_press_at(Team::Blue, start + secs(3));
+        game.tick_at(start + ms(6_001));
+
+        assert_eq!(game.decide(), GameResult::Won(Team::Blue));
+        assert_eq!(game.decide().winner(), Some(Team::Blue));
+    }
+
+    #[test]
+    fn double_rate_reaches_the_target_in_half_the_time() {
+        let config = GameConfig {
+            time_to_win: secs(10),
+            rate_red: 2.0,
+            ..Default::default()
+        };
+
+        let (mut game, start) = started_with(config);
+        game.button_press_at(Team::Red, start);
+        game.tick_at(start + ms(4_999));
+        assert_eq!(game.winner(), None);
+        game.tick_at(start + secs(5));
+        assert_eq!(game.winner(), Some(Team::Red));
+
+        // The other team still counts at wall clock pace
+        let (mut game, start) = started_with(config);
+        game.button_press_at(Team::Blue, start);
+        game.tick_at(start + secs(5));
+        assert_eq!(game.scores_at(start + secs(5)).blue(), secs(5));
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn unusable_rates_fall_back_to_the_default() {
+        for rate in [f32::NAN, f32::INFINITY, 0.0, -1.0] {
+            let config = GameConfig {
+                time_to_win: secs(10),
+                rate_red: rate,
+                rate_blue: rate,
+                ..Default::default()
+            };
+            let (mut game, start) = started_with(config);
+            assert_eq!(game.config().rate(Team::Red), default_rate());
+            assert_eq!(game.config().rate(Team::Blue), default_rate());
+
+            game.button_press_at(Team::Red, start);
+            game.tick_at(start + secs(4));
+            assert_eq!(game.scores_at(start + secs(4)).red(), secs(4));
+        }
+    }
+
+    #[test]
+    fn unheld_point_counts_as_neutral_time() {
+        let config = GameConfig {
+            time_to_win: secs(60),
+            start_neutral_penalty: true,
+            ..Default::default()
+        };
+        let (mut game, start) = started_with(config);
+
+        game.tick_at(start + secs(2));
+        game.tick_at(start + secs(5));
+        let scores = game.scores_at(start + secs(5));
+        assert_eq!(scores.neutral(), secs(5));
+        assert_eq!(scores.red(), Duration::ZERO);
+        assert_eq!(scores.blue(), Duration::ZERO);
+
+        // Taken, the point stops counting as neutral
+        game.button_press_at(Team::Red, start + secs(5));
+        game.tick_at(start + secs(8));
+        let scores = game.scores_at(start + secs(8));
+        assert_eq!(scores.neutral(), secs(5));
+        assert_eq!(scores.red(), secs(3));
+    }
+
+    #[test]
+    fn neutral_time_is_only_kept_when_asked_for() {
+        let (mut game, start) = started(60);
+        game.tick_at(start + secs(5));
+        assert_eq!(game.scores_at(start + secs(5)).neutral(), Duration::ZERO);
+    }
+
+    #[test]
+    fn hours_long_game_stays_within_the_cap() {
+        let target = secs(10 * 60);
+        let config = GameConfig {
+            time_to_win: target,
+            max_duration: None,
+            ..Default::default()
+        };
+        let (mut game, start) = started_with(config);
+        game.button_press_at(Team::Red, start);
+
+        // A stuck game nobody ended, ticking every second for five hours
+        let end = 5 * 60 * 60;
+        for sec in 1..=end {
+            let now = start + secs(sec);
+            if sec % 3_600 == 0 {
+                game.button_press_at(Team::Blue, now);
+            }
+            game.tick_at(now);
+        }
+        // Then a long gap between two ticks
+        let now = start + secs(end) + secs(5 * 60 * 60);
+        game.tick_at(now);
+
+        let scores = game.scores_at(now);
+        assert_eq!(scores.red(), target * 2);
+        assert_eq!(scores.blue(), target * 2);
+        assert_eq!(millis::to_u64(&scores.red()), 20 * 60 * 1_000);
+        assert_eq!(millis::to_u64(&game.elapsed_at(now)), 10 * 60 * 60 * 1_000);
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let config = GameConfig {
+            time_to_win: Duration::ZERO,
+            rate_blue: f32::NAN,
+            ..Default::default()
+        };
+        let errors = ConfigErrors(config.validate());
+        let fields: Vec<_> = errors.0.iter().map(|err| err.field).collect();
+        assert_eq!(fields, ["time_to_win_ms", "handicap_red_ms", "handicap_blue_ms", "rate_blue"]);
+        let message = errors.to_string();
+        assert!(fields.iter().all(|field| message.contains(field)));
+    }
+
+    #[test]
+    fn armed_game_can_start_within_the_window() {
+        let mut arming = Arming::default();
+        let now = Instant::now();
+        assert!(!arming.is_armed_at(now));
+
+        arming.arm_at(now);
+        assert!(arming.is_armed_at(now + ARM_WINDOW - ms(1)));
+        // Starting uses the arm up
+        arming.disarm();
+        assert!(!arming.is_armed_at(now + secs(1)));
+    }
+
+    #[test]
+    fn arm_runs_out_after_the_window() {
+        let mut arming = Arming::default();
+        let now = Instant::now();
+        arming.arm_at(now);
+        assert!(!arming.is_armed_at(now + ARM_WINDOW));
+
+        // Arming again opens a new window
+        arming.arm_at(now + ARM_WINDOW);
+        assert!(arming.is_armed_at(now + ARM_WINDOW + secs(1)));
+    }
+
+    #[test]
+    fn disarm_clears_the_arm() {
+        let mut arming = Arming::default();
+        let now = Instant::now();
+        arming.arm_at(now);
+        arming.disarm();
+        assert!(!arming.is_armed_at(now));
+        // Disarming twice is harmless
+        arming.disarm();
+        assert!(!arming.is_armed_at(now));
+    }
+}
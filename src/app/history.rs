@@ -0,0 +1,69 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::game::{millis, GameResult, Scores};
+
+/// NVS key the finished games are kept under
+pub const HISTORY_KEY: &str = "history";
+
+/// Finished games kept, the oldest goes once there are more
+pub const HISTORY_LEN: usize = 10;
+
+/// A finished game, for a recap of the session
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PastGame {
+    pub result: GameResult,
+    pub red_ms: u64,
+    pub blue_ms: u64,
+    pub captures_red: u32,
+    pub captures_blue: u32,
+    pub duration_ms: u64,
+    /// When it ended, seconds since the Unix epoch, or since boot when `relative`
+    pub ended_at_secs: u64,
+    /// The clock wasn't set, `ended_at_secs` only orders games within one boot
+    pub relative: bool,
+    /// Awarded by the referee because the other team left, whatever the scores said
+    #[serde(default)]
+    pub forfeit: bool,
+}
+
+impl PastGame {
+    /// Record a game ending now. `scores` as of the end, `duration` being its wall clock time.
+    /// `wall_clock` is the time if the clock was synced.
+    pub fn new(
+        result: GameResult,
+        scores: Scores,
+        duration: Duration,
+        wall_clock: Option<SystemTime>,
+    ) -> Self {
+        // Never synced, the clock has been counting from the epoch since boot
+        let relative = wall_clock.is_none();
+        let now = wall_clock
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Self {
+            result,
+            red_ms: millis::to_u64(&scores.red()),
+            blue_ms: millis::to_u64(&scores.blue()),
+            captures_red: scores.captures_red(),
+            captures_blue: scores.captures_blue(),
+            duration_ms: millis::to_u64(&duration),
+            ended_at_secs: now,
+            relative,
+            forfeit: false,
+        }
+    }
+}
+
+/// Add `game` as the newest entry, rotating the oldest ones out
+pub fn push(history: &mut VecDeque<PastGame>, game: PastGame) {
+    history.push_back(game);
+    while history.len() > HISTORY_LEN {
+        history.pop_front();
+    }
+}
@@ -1,19 +1,45 @@
+mod audio;
+mod broadcast;
+mod config;
 mod game;
+mod history;
+mod recovery;
 
 use std::{
     fmt::Debug,
-    sync::{Arc, OnceLock, mpsc}, time::Duration,
+    panic::{self, AssertUnwindSafe},
+    collections::VecDeque,
+    sync::{Arc, OnceLock, mpsc}, time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
-use esp_idf_svc::hal::delay::FreeRtos;
-use game::GameState;
+use esp_idf_svc::{bt::BdAddr, hal::delay::FreeRtos};
+use game::{Arming, GameState, HoldGate};
+use serde::Serialize;
 
-pub use game::{Scores, Team};
+pub use audio::{AudioSink, NullSink};
+pub use broadcast::{Change, StateBroadcaster, StateUpdate};
+pub use config::{AdminAction, AppConfig, AudioOutput, BoardRole};
+pub use game::{
+    BelowHeldTime, Checkpoint, ConfigErrors, FieldError, GameConfig, GameResult, OwnerSpan,
+    Phase, PressOutcome, Scores, Team, ARM_WINDOW,
+};
+pub use history::PastGame;
+pub use recovery::RecoverableGame;
+pub(crate) use game::millis;
+
+use history::HISTORY_KEY;
+use recovery::{SavedGame, CHECKPOINT_INTERVAL, CHECKPOINT_KEY, CONFIG_KEY};
 
 use crate::{
-    assets::{BLUE_TEAM_CAPTURE_SOUND, RED_TEAM_CAPTURE_SOUND},
-    hardware::{bt::BluetoothAudio, wifi::Wifi},
+    assets,
+    hardware::{
+        bt::{AudioConfig, AudioFormat, AudioStream, BluetoothAudio, BtDevice},
+        buzzer::{Buzzer, Melodies},
+        outputs::GpioSignal,
+        storage::Storage,
+        wifi::{JoinError, StationInfo, Wifi, WifiMode},
+    },
 };
 
 pub enum AppEvent {
@@ -21,7 +47,124 @@ pub enum AppEvent {
     Query(Box<dyn FnOnce(&App) + Send>),
 }
 
-#[derive(Debug, Clone, Copy)]
+/// NVS wear, `None` fields when NVS couldn't be opened
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageStats {
+    /// Writes and erases that reached flash since boot
+    pub nvs_writes: Option<u32>,
+    /// Deferred values are waiting for the next flush
+    pub nvs_dirty: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    pub audio: bool,
+    pub handler_panics: u32,
+    /// Current time between game loop passes
+    pub loop_interval_ms: u64,
+}
+
+/// An action that needs a running game was attempted without one
+#[derive(Debug)]
+pub struct NoGameRunning;
+
+impl std::fmt::Display for NoGameRunning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("No game is running")
+    }
+}
+
+impl std::error::Error for NoGameRunning {}
+
+/// A game start without arming first, or after the arming ran out
+#[derive(Debug)]
+pub struct NotArmed;
+
+impl std::fmt::Display for NotArmed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Not armed")
+    }
+}
+
+impl std::error::Error for NotArmed {}
+
+/// Roll call is on and a team didn't confirm it's at the point yet
+#[derive(Debug)]
+pub struct NotReady;
+
+impl std::fmt::Display for NotReady {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Teams not ready")
+    }
+}
+
+impl std::error::Error for NotReady {}
+
+/// Which teams pressed their button since the game was armed, see `AppConfig::roll_call`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ReadyState {
+    pub red: bool,
+    pub blue: bool,
+}
+
+impl ReadyState {
+    /// `true` if `team` wasn't ready yet
+    fn mark(&mut self, team: Team) -> bool {
+        let ready = match team {
+            Team::Red => &mut self.red,
+            Team::Blue => &mut self.blue,
+        };
+        !std::mem::replace(ready, true)
+    }
+
+    fn all(&self) -> bool {
+        self.red && self.blue
+    }
+}
+
+/// What a live scoreboard needs, sampled at one instant
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LiveState {
+    pub active: bool,
+    pub owner: Option<Team>,
+    pub scores: Scores,
+}
+
+/// Copy of the app state at one instant, everything the web UI needs on load.
+/// Cheap to take inside the loop, serializing it is left to the caller.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppSnapshot {
+    pub app_state: AppState,
+    /// `None` between games
+    pub phase: Option<Phase>,
+    #[serde(flatten)]
+    pub live: LiveState,
+    /// Held time each team still needs to win
+    pub remaining_red_ms: u64,
+    pub remaining_blue_ms: u64,
+    /// The team went past `GameConfig::warning_threshold` this game
+    pub warned_red: bool,
+    pub warned_blue: bool,
+    /// Roll call before the next game, only meaningful while `roll_call` is on
+    pub ready: ReadyState,
+    pub roll_call: bool,
+    pub config: GameConfig,
+    /// How the last game ended, to show after it's over
+    pub last_result: Option<GameResult>,
+    pub audio_connected: bool,
+    pub audio_healthy: bool,
+    pub handler_panics: u32,
+}
+
+/// Why a game can or can't be started right now
+#[derive(Debug, Clone, Serialize)]
+pub struct StartPreconditions {
+    pub can_start: bool,
+    pub failed: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AppState {
     Setup,
     Idle,
@@ -32,24 +175,137 @@ pub enum AppState {
 pub struct App {
     app_state: AppState,
     current_game: GameState,
+    /// The event bus: a plain channel owned by this App, not an IDF event loop source, so
+    /// there's no source name to collide with another subsystem's
     receiver: mpsc::Receiver<AppEvent>,
     sender: mpsc::Sender<AppEvent>,
     wifi: Wifi,
-    bluetooth_audio: Arc<BluetoothAudio>,
+    audio: Arc<dyn AudioSink>,
+    /// Set when the sink is Bluetooth, for pairing/discovery
+    bluetooth: Option<Arc<BluetoothAudio>>,
+    /// Plays `melodies` on captures alongside `audio`, `None` if it failed to start
+    buzzer: Option<Buzzer>,
+    melodies: Melodies,
+    /// Output for props, high while a game runs, `None` on boards without one
+    signal: Option<GpioSignal>,
+    config: AppConfig,
+    last_activity: Instant,
+    asleep: bool,
+    /// See `AppClient::arm`
+    arming: Arming,
+    /// Roll call for the armed game, reset by arming
+    ready: ReadyState,
+    /// Each button's hold, for `GameConfig::min_hold`
+    hold_red: HoldGate,
+    hold_blue: HoldGate,
+    /// Last emergency stop that went through, see `AppClient::emergency_stop`
+    last_emergency_stop: Option<Instant>,
+    /// Last attract mode jingle, and whether it may still be playing
+    last_attract: Instant,
+    attracting: bool,
+    handler_panics: u32,
+    /// Wi-Fi mode switch waiting to run, and when
+    pending_wifi_mode: Option<(WifiMode, Instant)>,
+    /// Why the last attempt to join a network failed, `None` if it didn't
+    wifi_join_error: Option<JoinError>,
+    broadcaster: StateBroadcaster,
+    /// Last update handed to the broadcaster, and when
+    last_update: Option<(StateUpdate, Instant)>,
+    /// Where the running game is checkpointed, `None` if NVS couldn't be opened
+    storage: Option<Storage>,
+    last_checkpoint: Instant,
+    /// Game found checkpointed at boot, until it's resumed or a new one starts
+    recoverable: Option<SavedGame>,
+    /// Phase as of the last loop pass, to notice changes
+    phase: Option<Phase>,
+    /// How the last game ended, `None` until one did
+    last_result: Option<GameResult>,
+    /// Last `HISTORY_LEN` finished games, oldest first, mirrored to NVS
+    history: VecDeque<PastGame>,
 }
 
+/// Scanned devices that haven't answered for this long are likely out of range
+const BT_DEVICE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// NVS key the buzzer melodies are kept under
+const MELODIES_KEY: &str = "melodies";
+
+/// NVS key the board role is kept under
+const ROLE_KEY: &str = "role";
+
+/// Presses of the stop button this soon after one that went through are ignored
+const EMERGENCY_STOP_COOLDOWN: Duration = Duration::from_secs(2);
+
+/// How long the game signal stays high after a game is won, a draw or a reset drops it at once
+const WIN_PULSE_MS: u32 = 5_000;
+
+/// Head start given to the HTTP response before a Wi-Fi switch drops the connection
+const WIFI_SWITCH_DELAY: Duration = Duration::from_millis(500);
+
 impl App {
-    pub fn init(wifi: Wifi, bt: Arc<BluetoothAudio>) -> Self {
+    pub fn init(
+        wifi: Wifi,
+        audio: Arc<dyn AudioSink>,
+        bluetooth: Option<Arc<BluetoothAudio>>,
+        buzzer: Option<Buzzer>,
+        signal: Option<GpioSignal>,
+        storage: Option<Storage>,
+        mut config: AppConfig,
+    ) -> Self {
         let (tx, rx) = mpsc::channel::<AppEvent>();
+        if let Some(role) = storage.as_ref().and_then(load_role) {
+            config.role = role;
+        }
+        let recoverable = storage.as_ref().and_then(load_checkpoint);
+        let history = storage
+            .as_ref()
+            .and_then(load_history)
+            .unwrap_or_default();
+        let game_config = storage
+            .as_ref()
+            .and_then(load_game_config)
+            .unwrap_or_default();
+        let melodies = storage
+            .as_ref()
+            .and_then(load_melodies)
+            .unwrap_or_default();
         let app = Self {
             app_state: AppState::Setup,
-            current_game: GameState::default(),
+            current_game: GameState::with_config(game_config),
             receiver: rx,
             sender: tx,
             wifi,
-            bluetooth_audio: bt,
+            audio,
+            bluetooth,
+            buzzer,
+            melodies,
+            signal,
+            config,
+            last_activity: Instant::now(),
+            asleep: false,
+            arming: Arming::default(),
+            ready: ReadyState::default(),
+            hold_red: HoldGate::default(),
+            hold_blue: HoldGate::default(),
+            last_emergency_stop: None,
+            last_attract: Instant::now(),
+            attracting: false,
+            handler_panics: 0,
+            pending_wifi_mode: None,
+            wifi_join_error: None,
+            broadcaster: StateBroadcaster::default(),
+            last_update: None,
+            storage,
+            last_checkpoint: Instant::now(),
+            recoverable,
+            phase: None,
+            last_result: None,
+            history,
         };
-        APP_CLIENT.set(app.client()).unwrap();
+        // `AppClient::get` hands out this one channel, a second App would have nobody sending to it
+        APP_CLIENT
+            .set(app.client())
+            .expect("App initialized twice, there's only one event channel");
         app
     }
 
@@ -64,25 +320,380 @@ impl App {
         });
         loop {
             if self.current_game.active() {
-                self.current_game.tick();
+                let warned = self.current_game.tick();
+                if let Some(winner) = self.current_game.winner() {
+                    self.finish_game(GameResult::Won(winner));
+                } else if self.current_game.time_up() {
+                    log::info!(target: "game", "Time is up");
+                    self.finish_game(self.current_game.decide());
+                } else if self.current_game.past_max_duration() {
+                    log::warn!(
+                        target: "game",
+                        "Game ran for {:?}, past its cap, ending it",
+                        self.current_game.elapsed()
+                    );
+                    self.finish_game(self.current_game.decide());
+                } else if let Some(team) = warned {
+                    log::info!(target: "game", "{team:?} is close to winning");
+                    self.audio.play(assets::warning_sound());
+                }
+            }
+            let min_hold = self.current_game.config().min_hold;
+            if self.current_game.active() && !min_hold.is_zero() {
+                for team in [Team::Red, Team::Blue] {
+                    if self.hold_gate(team).poll(Instant::now(), min_hold) {
+                        log::info!(target: "game", "{team:?} held for {min_hold:?}");
+                        self.press(team);
+                    }
+                }
+            }
+            self.follow_phase();
+            if let Some(signal) = &self.signal {
+                signal.set_active(self.current_game.active());
+            }
+
+            let checkpoint_due = self.last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL;
+            if self.current_game.active() && checkpoint_due {
+                self.save_checkpoint();
+            }
+            if let Some(storage) = &self.storage {
+                if let Err(err) = storage.flush_if_due() {
+                    log::error!("Failed to write to NVS: {err}");
+                }
+            }
+
+            let idle = matches!(self.app_state, AppState::Setup | AppState::Idle);
+            if let (true, Some(timeout)) = (idle, self.config.idle_timeout) {
+                if !self.asleep && self.last_activity.elapsed() >= timeout {
+                    self.sleep();
+                }
+            }
+
+            let attract_due = self.config.attract_mode
+                && self.last_attract.elapsed() >= self.config.attract_interval;
+            if idle && !self.asleep && attract_due {
+                self.attract();
             }
 
             while let Ok(event) = self.receiver.try_recv() {
-                match event {
-                    AppEvent::Command(func) => {
-                        func(&mut self);
-                    }
-                    AppEvent::Query(func) => {
-                        func(&self);
-                    }
+                self.handle_event(event);
+            }
+
+            let switch_due = self
+                .pending_wifi_mode
+                .as_ref()
+                .is_some_and(|(_, due)| Instant::now() >= *due);
+            if switch_due {
+                if let Some((mode, _)) = self.pending_wifi_mode.take() {
+                    self.switch_wifi(mode).await;
+                }
+            }
+
+            self.broadcast_changes();
+
+            // Yield until the next pass is due, a command or query cuts the wait short
+            if let Ok(event) = self.receiver.recv_timeout(self.loop_interval()) {
+                self.handle_event(event);
+            }
+        }
+    }
+
+    async fn switch_wifi(&mut self, mode: WifiMode) {
+        log::info!(target: "wifi", "Switching Wi-Fi to {mode:?}");
+        self.wifi_join_error = None;
+        let Err(err) = self.wifi.set_mode(&mode).await else {
+            return;
+        };
+        log::error!(target: "wifi", "Failed to switch Wi-Fi mode: {err}");
+
+        if let WifiMode::Client { .. } = mode {
+            self.wifi_join_error = Some(
+                err.downcast::<JoinError>()
+                    .unwrap_or_else(|err| JoinError::Failed(err.to_string())),
+            );
+            // Stranded with no network otherwise, nobody could even see what went wrong
+            log::warn!(target: "wifi", "Hosting our own network again");
+            if let Err(err) = self.wifi.fall_back_to_ap().await {
+                log::error!(target: "wifi", "Failed to fall back to hosting a network: {err}");
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: AppEvent) {
+        // A panicking handler must not take the whole game loop down with it
+        let result = match event {
+            AppEvent::Command(func) => panic::catch_unwind(AssertUnwindSafe(|| func(self))),
+            AppEvent::Query(func) => panic::catch_unwind(AssertUnwindSafe(|| func(self))),
+        };
+        if result.is_err() {
+            self.handler_panics += 1;
+            log::error!(
+                "App event handler panicked ({} so far), skipping it",
+                self.handler_panics
+            );
+        }
+    }
+
+    /// Time between loop passes: short in a game so scores and sounds keep up with the
+    /// buttons, long otherwise to save power
+    fn loop_interval(&self) -> Duration {
+        match self.app_state {
+            AppState::InGame => self.config.game_loop_interval,
+            AppState::Setup | AppState::Idle => self.config.idle_loop_interval,
+        }
+    }
+
+    /// Which UI bundle to serve, fixed for this boot
+    pub fn role(&self) -> BoardRole {
+        self.config.role
+    }
+
+    /// Receive a `StateUpdate` whenever the game state changes
+    pub fn subscribe(&mut self) -> mpsc::Receiver<StateUpdate> {
+        let rx = self.broadcaster.subscribe();
+        // Start the newcomer off with the current state
+        self.last_update = None;
+        rx
+    }
+
+    /// Take a copy of the state for a query to hand back. Queries run on the game loop,
+    /// anything slow (JSON encoding, logs) belongs on the caller's side of the bus.
+    pub fn snapshot(&self) -> AppSnapshot {
+        let game = &self.current_game;
+        AppSnapshot {
+            app_state: self.app_state,
+            phase: game.phase(),
+            live: self.live_state(),
+            remaining_red_ms: millis::to_u64(&game.remaining(Team::Red)),
+            remaining_blue_ms: millis::to_u64(&game.remaining(Team::Blue)),
+            warned_red: game.warned(Team::Red),
+            warned_blue: game.warned(Team::Blue),
+            ready: self.ready,
+            roll_call: self.config.roll_call,
+            config: game.config(),
+            last_result: self.last_result,
+            audio_connected: self.audio.is_connected(),
+            audio_healthy: self.audio.is_healthy(),
+            handler_panics: self.handler_panics,
+        }
+    }
+
+    fn live_state(&self) -> LiveState {
+        LiveState {
+            active: self.current_game.active(),
+            owner: self.current_game.current_team(),
+            scores: self.current_game.scores(),
+        }
+    }
+
+    /// Push the current state to subscribers if it changed in a way they care about
+    fn broadcast_changes(&mut self) {
+        if self.broadcaster.subscriber_count() == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let update = StateUpdate {
+            app_state: self.app_state,
+            live: self.live_state(),
+        };
+
+        if let Some((last, sent_at)) = &self.last_update {
+            match update.change_since(last) {
+                None => return,
+                Some(Change::Score)
+                    if now.duration_since(*sent_at) < self.config.broadcast_interval =>
+                {
+                    // Picked up again on a later pass, once the interval is over
+                    return;
                 }
+                Some(Change::Score | Change::Lifecycle) => {}
+            }
+        }
+
+        self.broadcaster.publish(update);
+        self.last_update = Some((update, now));
+    }
+
+    /// Cue the players when the game moves into another phase
+    fn follow_phase(&mut self) {
+        let phase = self.current_game.phase();
+        if phase == self.phase {
+            return;
+        }
+        // Starting and ending have their own sounds, or none
+        if let (Some(_), Some(phase)) = (self.phase, phase) {
+            log::info!(target: "game", "Game entered {phase:?}");
+            self.audio.play(assets::phase_sound());
+        }
+        self.phase = phase;
+    }
+
+    fn save_checkpoint(&mut self) {
+        self.last_checkpoint = Instant::now();
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let Some(game) = self.current_game.checkpoint_at(self.last_checkpoint) else {
+            return;
+        };
+        // Reaches flash at most every `FLUSH_INTERVAL`, checkpoints come far more often
+        if let Err(err) = storage.set_deferred(CHECKPOINT_KEY, &SavedGame::new(game)) {
+            log::error!(target: "game", "Failed to checkpoint the game: {err}");
+        }
+    }
+
+    /// The game ended cleanly, there's nothing to recover anymore
+    fn clear_checkpoint(&mut self) {
+        self.recoverable = None;
+        if let Some(storage) = &self.storage {
+            if let Err(err) = storage.remove(CHECKPOINT_KEY) {
+                log::error!(target: "game", "Failed to clear the game checkpoint: {err}");
             }
+            // Whatever else was deferred goes out now rather than with the next flush
+            if let Err(err) = storage.flush() {
+                log::error!("Failed to write to NVS: {err}");
+            }
+        }
+    }
+
+    fn finish_game(&mut self, result: GameResult) {
+        self.end(result, false);
+    }
+
+    /// `forfeit` when the referee handed the win over, see `AppClient::forfeit`
+    fn end(&mut self, result: GameResult, forfeit: bool) {
+        let duration = self.current_game.elapsed();
+        self.current_game.stop();
+        self.clear_checkpoint();
+        self.app_state = AppState::Idle;
+        self.last_result = Some(result);
+        let scores = self.current_game.scores();
+        self.record_game(PastGame {
+            forfeit,
+            ..PastGame::new(result, scores, duration, self.wifi.now())
+        });
+        match result {
+            GameResult::Won(winner) => {
+                if forfeit {
+                    log::info!(target: "game", "{winner:#?} won the game by forfeit");
+                } else {
+                    log::info!(target: "game", "{winner:#?} won the game");
+                }
+                self.audio.play(assets::victory_sound(winner));
+                if let Some(signal) = &self.signal {
+                    signal.pulse(WIN_PULSE_MS);
+                }
+            }
+            GameResult::Draw => {
+                log::info!(target: "game", "Game ended in a draw");
+                self.audio.play(assets::draw_sound());
+            }
+            GameResult::NoWinner => log::info!(target: "game", "Game ended without a winner"),
+        }
+    }
+
+    fn record_game(&mut self, game: PastGame) {
+        history::push(&mut self.history, game);
+        if let Some(storage) = &self.storage {
+            if let Err(err) = storage.set(HISTORY_KEY, &self.history) {
+                log::error!(target: "game", "Failed to save the game history: {err}");
+            }
+        }
+    }
+
+    /// Drop into low-power mode: Wi-Fi power save on and the audio task paused
+    fn sleep(&mut self) {
+        log::info!("Idle for too long, entering low-power mode");
+        if let Err(err) = self.wifi.set_power_save(true) {
+            log::error!(target: "wifi", "Failed to enable Wi-Fi power save: {err}");
+        }
+        self.audio.set_awake(false);
+        self.asleep = true;
+    }
+
+    /// Attract mode jingle, doesn't count as activity so the idle timeout still applies
+    fn attract(&mut self) {
+        self.last_attract = Instant::now();
+        self.attracting = true;
+        self.audio.play(assets::attract_sound());
+    }
+
+    /// A press that counts, see `GameConfig::min_hold`
+    fn press(&mut self, team: Team) {
+        match self.current_game.button_press(team) {
+            PressOutcome::Captured(team) => {
+                self.audio.play(assets::capture_sound(team));
+                if let Some(buzzer) = &self.buzzer {
+                    buzzer.play_melody(self.melodies.get(team));
+                }
+            }
+            PressOutcome::AlreadyOwned | PressOutcome::Locked | PressOutcome::Ignored => {}
+        }
+    }
+
+    fn hold_gate(&mut self, team: Team) -> &mut HoldGate {
+        match team {
+            Team::Red => &mut self.hold_red,
+            Team::Blue => &mut self.hold_blue,
+        }
+    }
+
+    /// Cut the attract mode jingle short, players are here
+    fn stop_attract(&mut self) {
+        self.last_attract = Instant::now();
+        if std::mem::take(&mut self.attracting) {
+            self.audio.stop();
+        }
+    }
+
+    /// Register activity, coming back out of low-power mode if needed
+    fn wake(&mut self) {
+        self.last_activity = Instant::now();
+        if !self.asleep {
+            return;
+        }
+
+        log::info!("Activity detected, leaving low-power mode");
+        if let Err(err) = self.wifi.set_power_save(false) {
+            log::error!(target: "wifi", "Failed to disable Wi-Fi power save: {err}");
+        }
+        self.audio.set_awake(true);
+        self.asleep = false;
+    }
+
+    fn start_preconditions(&self) -> StartPreconditions {
+        let mut failed = vec![];
+
+        if !matches!(self.app_state, AppState::Idle | AppState::Setup) {
+            failed.push("device is busy");
+        }
+        if self.current_game.active() {
+            failed.push("a game is already running");
+        }
+        if !self.audio.is_connected() {
+            failed.push("speaker not connected");
+        }
+        if !self.audio.is_healthy() {
+            failed.push("audio task not running");
+        }
+        if !self.armed() {
+            failed.push("not armed");
+        }
+        if self.config.roll_call && !self.ready.all() {
+            failed.push("teams not ready");
+        }
 
-            // Yield for a little
-            FreeRtos::delay_ms(10);
+        StartPreconditions {
+            can_start: failed.is_empty(),
+            failed,
         }
     }
 
+    fn armed(&self) -> bool {
+        self.arming.is_armed_at(Instant::now())
+    }
+
     pub fn client(&self) -> AppClient {
         AppClient {
             bus: AppBus {
@@ -98,6 +709,8 @@ pub struct AppBus {
 }
 
 impl AppBus {
+    /// Run `action` on the game loop and hand its result back. Keep it short, the game
+    /// doesn't tick meanwhile: copy data out (`App::snapshot`) rather than encode it in there.
     pub fn query<R: Send + 'static, F: FnOnce(&App) -> R + Send + 'static>(
         &self,
         action: F,
@@ -122,10 +735,10 @@ impl AppBus {
         Ok(response)
     }
 
-    pub fn command<F: FnOnce(&mut App) -> anyhow::Result<()> + Send + 'static>(
+    pub fn command<R: Send + 'static, F: FnOnce(&mut App) -> anyhow::Result<R> + Send + 'static>(
         &self,
         action: F,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<R> {
         let (tx, rx) = mpsc::channel();
 
         let function = move |app: &mut App| {
@@ -150,33 +763,579 @@ pub struct AppClient {
 }
 
 impl AppClient {
-    pub fn start_game(&self) -> anyhow::Result<()> {
+    /// Allow `start_game` for the next `ARM_WINDOW`, against starting one by accident
+    pub fn arm(&self) -> anyhow::Result<()> {
+        self.bus.command(|app| {
+            app.arming.arm_at(Instant::now());
+            app.ready = ReadyState::default();
+            log::info!(target: "game", "Armed for {}s", ARM_WINDOW.as_secs());
+            Ok(())
+        })
+    }
+
+    pub fn disarm(&self) -> anyhow::Result<()> {
         self.bus.command(|app| {
+            app.arming.disarm();
+            app.ready = ReadyState::default();
+            Ok(())
+        })
+    }
+
+    /// Fails with `NotArmed` unless `arm` was called within `ARM_WINDOW`, starting disarms.
+    /// With roll call on it also fails with `NotReady` until both teams pressed their
+    /// button, unless `force`d by the admin.
+    pub fn start_game(&self, force: bool) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
             if app.current_game.active() {
-                app.current_game.start();
+                return Err(anyhow!("A game is already running"));
+            }
+            if !app.armed() {
+                return Err(NotArmed.into());
+            }
+            if app.config.roll_call && !app.ready.all() {
+                if !force {
+                    return Err(NotReady.into());
+                }
+                log::warn!(target: "game", "Admin override: starting with {:?}", app.ready);
             }
+            app.arming.disarm();
+            app.ready = ReadyState::default();
+            app.stop_attract();
+            app.current_game.start();
+            app.app_state = AppState::InGame;
+            // Its checkpoints take the old game's place
+            app.recoverable = None;
             Ok(())
         })?;
 
         Ok(())
     }
 
-    pub fn team_press(&self, team: Team) -> anyhow::Result<()> {
-        log::info!("Team press {team:#?}");
+    /// Game cut short by a reboot that can be picked back up with `resume_game`
+    pub fn recoverable_game(&self) -> anyhow::Result<Option<RecoverableGame>> {
+        self.bus
+            .query(|app| app.recoverable.as_ref().map(RecoverableGame::from))
+    }
+
+    /// Resume the game found at boot. `false` if there's none.
+    pub fn resume_game(&self) -> anyhow::Result<bool> {
+        self.bus.command(|app| {
+            if app.current_game.active() {
+                return Err(anyhow!("A game is already running"));
+            }
+            let Some(saved) = app.recoverable.take() else {
+                return Ok(false);
+            };
+            app.stop_attract();
+            app.current_game.resume_at(saved.game, Instant::now());
+            app.app_state = AppState::InGame;
+            app.last_checkpoint = Instant::now();
+            Ok(true)
+        })
+    }
+
+    /// End the running game, `winner` gets the victory sound
+    pub fn end_game(&self, winner: Option<Team>) -> anyhow::Result<()> {
         self.bus.command(move |app| {
-            match team {
-                Team::Blue => {
-                    app.bluetooth_audio.play_audio(BLUE_TEAM_CAPTURE_SOUND);
+            if !app.current_game.active() {
+                return Err(NoGameRunning.into());
+            }
+            app.finish_game(winner.map_or(GameResult::NoWinner, GameResult::Won));
+            Ok(())
+        })
+    }
+
+    /// End the running game early, whoever held the point longest wins, a tie is a draw
+    pub fn end_and_declare(&self) -> anyhow::Result<GameResult> {
+        self.bus.command(|app| {
+            if !app.current_game.active() {
+                return Err(NoGameRunning.into());
+            }
+            // Account for the time up to now before comparing
+            app.current_game.tick();
+            let result = app.current_game.decide();
+            app.finish_game(result);
+            Ok(result)
+        })
+    }
+
+    /// `loser` left the field, the other team wins whatever the scores. Fails with
+    /// `NoGameRunning` between games.
+    pub fn forfeit(&self, loser: Team) -> anyhow::Result<Team> {
+        self.bus.command(move |app| {
+            if !app.current_game.active() {
+                return Err(NoGameRunning.into());
+            }
+            log::warn!(target: "game", "Admin override: {loser:?} forfeits");
+            // Held time up to now still goes into the record
+            app.current_game.tick();
+            let winner = loser.other();
+            app.end(GameResult::Won(winner), true);
+            Ok(winner)
+        })
+    }
+
+    pub fn start_preconditions(&self) -> anyhow::Result<StartPreconditions> {
+        self.bus.query(|app| app.start_preconditions())
+    }
+
+    pub fn can_start(&self) -> anyhow::Result<bool> {
+        Ok(self.start_preconditions()?.can_start)
+    }
+
+    /// Why joining the last network failed, `None` if it worked or none was tried
+    pub fn wifi_join_error(&self) -> anyhow::Result<Option<JoinError>> {
+        self.bus.query(|app| app.wifi_join_error.clone())
+    }
+
+    /// See `Wifi::connected_stations`
+    pub fn wifi_stations(&self) -> anyhow::Result<Vec<StationInfo>> {
+        self.bus.query(|app| app.wifi.connected_stations())?
+    }
+
+    /// Switch Wi-Fi mode. Happens shortly after this returns, so the request asking for it
+    /// can still be answered before its connection goes away.
+    pub fn set_wifi_mode(&self, mode: WifiMode) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            app.pending_wifi_mode = Some((mode, Instant::now() + WIFI_SWITCH_DELAY));
+            Ok(())
+        })
+    }
+
+    pub fn game_config(&self) -> anyhow::Result<GameConfig> {
+        self.bus.query(|app| app.current_game.config())
+    }
+
+    /// `false` if it wasn't applied because a game is running
+    pub fn set_game_config(&self, config: GameConfig) -> anyhow::Result<bool> {
+        self.bus
+            .command(move |app| Ok(app.current_game.set_config(config).is_ok()))
+    }
+
+    /// Correct `time_to_win`, even mid-game. If a team is past the new target it wins right away,
+    /// that team is returned. Fails with `BelowHeldTime` for such a target unless `force`, and
+    /// with a `FieldError` for a target no game can be played to.
+    pub fn adjust_time_to_win(
+        &self,
+        time_to_win: Duration,
+        force: bool,
+    ) -> anyhow::Result<Option<Team>> {
+        self.bus.command(move |app| {
+            app.current_game.adjust_time_to_win(time_to_win, force)?;
+            if !app.current_game.active() {
+                return Ok(None);
+            }
+            let winner = app.current_game.winner();
+            if let Some(winner) = winner {
+                app.finish_game(GameResult::Won(winner));
+            }
+            Ok(winner)
+        })
+    }
+
+    /// Add or take off held time of `team` by hand. If that takes it to its target it wins
+    /// right away, that team is returned. Fails with `NoGameRunning` between games.
+    pub fn adjust_time(&self, team: Team, delta_secs: i32) -> anyhow::Result<Option<Team>> {
+        self.bus.command(move |app| {
+            if !app.current_game.adjust_time(team, delta_secs) {
+                return Err(NoGameRunning.into());
+            }
+            log::warn!(target: "game", "Admin override: {delta_secs:+}s held time for {team:?}");
+            let winner = app.current_game.winner();
+            if let Some(winner) = winner {
+                app.finish_game(GameResult::Won(winner));
+            }
+            Ok(winner)
+        })
+    }
+
+    /// Replace the whole config in one go and keep it for the next boot.
+    /// Fails with `ConfigErrors` if `config` isn't valid, `false` if it wasn't applied
+    /// because a game is running.
+    pub fn configure_game(&self, config: GameConfig) -> anyhow::Result<bool> {
+        let errors = config.validate();
+        if !errors.is_empty() {
+            return Err(ConfigErrors(errors).into());
+        }
+        self.bus.command(move |app| {
+            if app.current_game.set_config(config).is_err() {
+                return Ok(false);
+            }
+            if let Some(storage) = &app.storage {
+                if let Err(err) = storage.set(CONFIG_KEY, &config) {
+                    log::error!(target: "game", "Failed to save the game config: {err}");
                 }
-                Team::Red => {
-                    app.bluetooth_audio.play_audio(RED_TEAM_CAPTURE_SOUND);
+            }
+            Ok(true)
+        })
+    }
+
+    pub fn live_state(&self) -> anyhow::Result<LiveState> {
+        self.bus.query(|app| app.live_state())
+    }
+
+    /// Receive a `StateUpdate` whenever the game state changes, see `App::subscribe`
+    pub fn subscribe(&self) -> anyhow::Result<mpsc::Receiver<StateUpdate>> {
+        self.bus.command(|app| Ok(app.subscribe()))
+    }
+
+    /// Everything about the app worth showing, see `App::snapshot`
+    pub fn snapshot(&self) -> anyhow::Result<AppSnapshot> {
+        self.bus.query(|app| app.snapshot())
+    }
+
+    /// Last finished games, oldest first
+    pub fn game_history(&self) -> anyhow::Result<Vec<PastGame>> {
+        self.bus.query(|app| app.history.iter().copied().collect())
+    }
+
+    /// The newest entry of `game_history`
+    pub fn last_game(&self) -> anyhow::Result<Option<PastGame>> {
+        self.bus.query(|app| app.history.back().copied())
+    }
+
+    /// Who held the point when in the current or last game
+    pub fn owner_history(&self) -> anyhow::Result<Vec<OwnerSpan>> {
+        self.bus.query(|app| app.current_game.owner_history().to_vec())
+    }
+
+    pub fn scores(&self) -> anyhow::Result<Scores> {
+        self.bus.query(|app| app.current_game.scores())
+    }
+
+    pub fn team_press(&self, team: Team) -> anyhow::Result<()> {
+        log::info!(target: "game", "Team press {team:#?}");
+        self.bus.command(move |app| {
+            app.wake();
+            app.stop_attract();
+            // Before a game a press answers the roll call
+            let roll_call = app.config.roll_call && !app.current_game.active() && app.armed();
+            if roll_call {
+                if app.ready.mark(team) {
+                    log::info!(target: "game", "{team:?} is ready");
+                    if let Some(buzzer) = &app.buzzer {
+                        buzzer.play_melody(app.melodies.get(team));
+                    }
                 }
+                return Ok(());
+            }
+            // With a minimum hold the press is counted off `team_held` instead
+            if app.current_game.config().min_hold.is_zero() {
+                app.press(team);
             }
             Ok(())
         })?;
         Ok(())
     }
 
+    /// `team`'s button went down or came back up. Only matters with `GameConfig::min_hold`
+    /// set, the app loop counts a press once the hold lasted that long.
+    pub fn team_held(&self, team: Team, held: bool) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            let min_hold = app.current_game.config().min_hold;
+            let released_late = app.hold_gate(team).set_held(held, Instant::now(), min_hold);
+            if released_late && !min_hold.is_zero() && app.current_game.active() {
+                log::info!(target: "game", "{team:?} held for {min_hold:?}");
+                app.press(team);
+            }
+            Ok(())
+        })
+    }
+
+    /// Hand the point to `team`, or nobody, by hand
+    pub fn set_owner(&self, team: Option<Team>) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            let previous = app.current_game.current_team();
+            if !app.current_game.set_owner(team) {
+                return Err(NoGameRunning.into());
+            }
+            log::warn!(
+                target: "game",
+                "Admin override: owner changed from {previous:?} to {team:?}"
+            );
+            if let Some(team) = team {
+                app.audio.play(assets::capture_sound(team));
+            }
+            Ok(())
+        })
+    }
+
+    /// Both buttons were held down, run the configured `AdminAction`
+    pub fn admin_combo(&self) -> anyhow::Result<()> {
+        self.bus.command(|app| {
+            app.wake();
+            if !app.current_game.active() {
+                return Ok(());
+            }
+            let action = app.config.combo_action;
+            log::info!(target: "game", "Button combo: {action:?}");
+            match action {
+                AdminAction::EndGame => {
+                    app.current_game.tick();
+                    let result = app.current_game.decide();
+                    app.finish_game(result);
+                }
+                AdminAction::Reset => app.finish_game(GameResult::NoWinner),
+            }
+            Ok(())
+        })
+    }
+
+    /// The stop button was pressed: end any game without a winner and silence every output.
+    /// Only goes through the event bus, so it works with the HTTP stack wedged. `false` if
+    /// ignored, within `EMERGENCY_STOP_COOLDOWN` of the last one.
+    pub fn emergency_stop(&self) -> anyhow::Result<bool> {
+        self.bus.command(|app| {
+            let now = Instant::now();
+            let cooling_down = app
+                .last_emergency_stop
+                .is_some_and(|last| now.duration_since(last) < EMERGENCY_STOP_COOLDOWN);
+            if cooling_down {
+                return Ok(false);
+            }
+            app.last_emergency_stop = Some(now);
+
+            log::warn!(target: "game", "EMERGENCY STOP pressed");
+            app.wake();
+            app.arming.disarm();
+            app.ready = ReadyState::default();
+            if app.current_game.active() {
+                app.finish_game(GameResult::NoWinner);
+            }
+            app.attracting = false;
+            app.last_attract = now;
+            app.audio.stop();
+            if let Some(buzzer) = &app.buzzer {
+                buzzer.stop();
+            }
+            Ok(true)
+        })
+    }
+
+    /// Mark the device as in use, waking it up if it was idling in low-power mode
+    pub fn wake(&self) -> anyhow::Result<()> {
+        self.bus.command(|app| {
+            app.wake();
+            Ok(())
+        })
+    }
+
+    /// Change the idle timeout, `None` disables auto-sleep
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            app.config.idle_timeout = timeout;
+            app.wake();
+            Ok(())
+        })
+    }
+
+    /// Turn the pre-game roll call on or off, see `AppConfig::roll_call`
+    pub fn set_roll_call(&self, enabled: bool) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            app.config.roll_call = enabled;
+            Ok(())
+        })
+    }
+
+    /// Turn attract mode on or off, see `AppConfig::attract_mode`
+    pub fn set_attract_mode(&self, enabled: bool) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            app.config.attract_mode = enabled;
+            if !enabled {
+                app.stop_attract();
+            }
+            Ok(())
+        })
+    }
+
+    pub fn role(&self) -> anyhow::Result<BoardRole> {
+        self.bus.query(|app| app.config.role)
+    }
+
+    /// Keep `role` for the next boot, the UI routes are only registered at boot. Fails
+    /// without NVS, there'd be nowhere to keep it.
+    pub fn set_role(&self, role: BoardRole) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            let storage = app
+                .storage
+                .as_ref()
+                .ok_or_else(|| anyhow!("NVS is unavailable, the role can't be saved"))?;
+            storage.set(ROLE_KEY, &role)?;
+            log::info!("Board role set to {role:?}, takes effect after a reboot");
+            Ok(())
+        })
+    }
+
+    pub fn melodies(&self) -> anyhow::Result<Melodies> {
+        self.bus.query(|app| app.melodies.clone())
+    }
+
+    /// Change the buzzer's capture melodies and keep them for the next boot.
+    /// Fails if they don't pass `Melodies::validate`.
+    pub fn set_melodies(&self, melodies: Melodies) -> anyhow::Result<()> {
+        melodies.validate()?;
+        self.bus.command(move |app| {
+            if let Some(storage) = &app.storage {
+                if let Err(err) = storage.set(MELODIES_KEY, &melodies) {
+                    log::error!("Failed to save the melodies: {err}");
+                }
+            }
+            app.melodies = melodies;
+            Ok(())
+        })
+    }
+
+    /// Change the minimum gap between score updates to subscribers
+    pub fn set_broadcast_interval(&self, interval: Duration) -> anyhow::Result<()> {
+        self.bus.command(move |app| {
+            app.config.broadcast_interval = interval;
+            Ok(())
+        })
+    }
+
+    pub fn health(&self) -> anyhow::Result<Health> {
+        self.bus.query(|app| Health {
+            audio: app.audio.is_healthy(),
+            handler_panics: app.handler_panics,
+            loop_interval_ms: millis::to_u64(&app.loop_interval()),
+        })
+    }
+
+    pub fn storage_stats(&self) -> anyhow::Result<StorageStats> {
+        self.bus.query(|app| StorageStats {
+            nvs_writes: app.storage.as_ref().map(Storage::writes),
+            nvs_dirty: app.storage.as_ref().map(Storage::dirty),
+        })
+    }
+
+    /// Play the test beep, or a team's capture clip, without touching the game.
+    /// `false` if there's no speaker to play it on.
+    pub fn play_test_sound(&self, team: Option<Team>) -> anyhow::Result<bool> {
+        self.bus.command(move |app| {
+            if !app.audio.is_connected() {
+                return Ok(false);
+            }
+            let clip = match team {
+                Some(team) => assets::capture_sound(team),
+                None => assets::test_beep(),
+            };
+            app.audio.play(clip);
+            Ok(true)
+        })
+    }
+
+    /// Play `clip` as is, `false` if there's no speaker to play it on
+    pub fn play_clip(&self, clip: &'static [u8]) -> anyhow::Result<bool> {
+        self.bus.command(move |app| {
+            if !app.audio.is_connected() {
+                return Ok(false);
+            }
+            app.audio.play(clip);
+            Ok(true)
+        })
+    }
+
+    pub fn audio_format(&self) -> anyhow::Result<Option<AudioFormat>> {
+        self.bus
+            .query(|app| app.bluetooth.as_ref().and_then(|bt| bt.audio_format()))
+    }
+
+    pub fn audio_config(&self) -> anyhow::Result<AudioConfig> {
+        Ok(self.bluetooth()?.audio_config())
+    }
+
+    /// Fails with `BtError::InvalidAudioConfig` for values the audio task can't use
+    pub fn set_audio_config(&self, config: AudioConfig) -> anyhow::Result<()> {
+        self.bluetooth()?.set_audio_config(config)
+    }
+
+    /// Take the speaker over for live audio. `None` if no speaker is connected.
+    pub fn audio_stream(&self) -> anyhow::Result<Option<AudioStream>> {
+        let bt = self.bluetooth()?;
+        if !bt.is_connected() {
+            return Ok(None);
+        }
+        Ok(Some(bt.stream_audio()))
+    }
+
+    /// The Bluetooth side of the audio sink, to use outside the game loop
+    fn bluetooth(&self) -> anyhow::Result<Arc<BluetoothAudio>> {
+        self.bus
+            .query(|app| app.bluetooth.clone())?
+            .ok_or_else(|| anyhow!("Audio isn't going through Bluetooth"))
+    }
+
+    /// Devices seen by recent scans, older ones are forgotten
+    pub fn bt_devices(&self) -> anyhow::Result<Vec<BtDevice>> {
+        let bt = self.bluetooth()?;
+        bt.prune_discovered(BT_DEVICE_TTL);
+        let devices = bt.discovered_devices().read().expect("Poisoned").clone();
+        Ok(devices)
+    }
+
+    /// See `BluetoothAudio::set_muted`
+    pub fn set_audio_muted(&self, muted: bool) -> anyhow::Result<()> {
+        self.bluetooth()?.set_muted(muted);
+        Ok(())
+    }
+
+    /// See `BluetoothAudio::set_suspend_when_idle`
+    pub fn bt_suspend_when_idle(&self, suspend: bool) -> anyhow::Result<()> {
+        self.bluetooth()?.set_suspend_when_idle(suspend);
+        Ok(())
+    }
+
+    /// See `BluetoothAudio::set_name_retries`
+    pub fn bt_name_retries(&self, retries: usize) -> anyhow::Result<()> {
+        self.bluetooth()?.set_name_retries(retries);
+        Ok(())
+    }
+
+    /// Speakers we paired with, see `BluetoothAudio::bonded_devices`
+    pub fn bt_bonded_devices(&self) -> anyhow::Result<Vec<BdAddr>> {
+        self.bluetooth()?.bonded_devices()
+    }
+
+    /// `false` if there was no bond with `addr`
+    pub fn bt_forget_device(&self, addr: BdAddr) -> anyhow::Result<bool> {
+        self.bluetooth()?.forget_device(addr)
+    }
+
+    pub fn bt_clear_devices(&self) -> anyhow::Result<()> {
+        self.bluetooth()?.clear_discovered();
+        Ok(())
+    }
+
+    /// Blocks for up to `duration`, on the caller's thread so the game loop keeps running
+    pub fn bt_discover(
+        &self,
+        duration: Duration,
+        max: usize,
+        audio_only: bool,
+        fresh: bool,
+    ) -> anyhow::Result<Vec<BtDevice>> {
+        let bt = self.bluetooth()?;
+        if audio_only {
+            bt.discover_audio_sinks(duration, max, fresh)
+        } else {
+            bt.discover_devices(duration, max, fresh)
+        }
+    }
+
+    pub fn bt_connect(&self, addr: BdAddr) -> anyhow::Result<()> {
+        let bt = self.bluetooth()?;
+        let device = bt
+            .discovered_devices()
+            .read()
+            .expect("Poisoned")
+            .iter()
+            .find(|device| device.addr() == addr)
+            .cloned()
+            .unwrap_or_else(|| BtDevice::from_addr(addr));
+
+        bt.a2dp_connect(&device)
+    }
+
     pub fn get() -> AppClient {
         let app_client = APP_CLIENT.get().expect("No app client initialized");
 
@@ -185,3 +1344,80 @@ impl AppClient {
 }
 
 static APP_CLIENT: OnceLock<AppClient> = OnceLock::new();
+
+/// Rules saved by `/game/configure`, if they're still valid
+fn load_game_config(storage: &Storage) -> Option<GameConfig> {
+    let config = match storage.get::<GameConfig>(CONFIG_KEY) {
+        Ok(config) => config?,
+        Err(err) => {
+            log::error!(target: "game", "Failed to read the saved game config: {err}");
+            return None;
+        }
+    };
+
+    let errors = config.validate();
+    if !errors.is_empty() {
+        let errors = ConfigErrors(errors);
+        log::warn!(target: "game", "Ignoring the saved game config, {errors}");
+        return None;
+    }
+    Some(config)
+}
+
+/// Melodies saved by `set_melodies`, if they're still valid
+fn load_melodies(storage: &Storage) -> Option<Melodies> {
+    let melodies = match storage.get::<Melodies>(MELODIES_KEY) {
+        Ok(melodies) => melodies?,
+        Err(err) => {
+            log::error!("Failed to read the saved melodies: {err}");
+            return None;
+        }
+    };
+
+    match melodies.validate() {
+        Ok(()) => Some(melodies),
+        Err(err) => {
+            log::warn!("Ignoring the saved melodies, {err}");
+            None
+        }
+    }
+}
+
+/// Role saved by `set_role`, if any
+fn load_role(storage: &Storage) -> Option<BoardRole> {
+    storage
+        .get(ROLE_KEY)
+        .inspect_err(|err| log::error!("Failed to read the saved board role: {err}"))
+        .ok()
+        .flatten()
+}
+
+fn load_history(storage: &Storage) -> Option<VecDeque<PastGame>> {
+    match storage.get(HISTORY_KEY) {
+        Ok(history) => history,
+        Err(err) => {
+            log::error!(target: "game", "Failed to read the game history: {err}");
+            None
+        }
+    }
+}
+
+/// A checkpoint left behind by a game that never ended, if it's recent enough to resume
+fn load_checkpoint(storage: &Storage) -> Option<SavedGame> {
+    let saved = match storage.get::<SavedGame>(CHECKPOINT_KEY) {
+        Ok(saved) => saved?,
+        Err(err) => {
+            log::error!(target: "game", "Failed to read the game checkpoint: {err}");
+            return None;
+        }
+    };
+
+    if !saved.is_recent() {
+        log::info!(target: "game", "Discarding a stale game checkpoint");
+        let _ = storage.remove(CHECKPOINT_KEY);
+        return None;
+    }
+
+    log::warn!(target: "game", "Found a game that was cut short, it can be resumed");
+    Some(saved)
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::game::Checkpoint;
+
+/// NVS key the running game is checkpointed under
+pub const CHECKPOINT_KEY: &str = "checkpoint";
+
+/// NVS key of the rules set through `/game/configure`, used again after a reboot
+pub const CONFIG_KEY: &str = "config";
+
+/// How often a running game is checkpointed, at most this much play is lost to a power cut
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Checkpoints older than this at boot are from another session, not a brownout
+pub const MAX_CHECKPOINT_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// A checkpoint as stored in NVS
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SavedGame {
+    /// Device clock at save time, seconds since the Unix epoch
+    saved_at_secs: u64,
+    pub game: Checkpoint,
+}
+
+impl SavedGame {
+    pub fn new(game: Checkpoint) -> Self {
+        Self {
+            saved_at_secs: now_secs(),
+            game,
+        }
+    }
+
+    /// `None` if the clock went back since, it restarts from zero after a full power loss
+    pub fn age(&self) -> Option<Duration> {
+        now_secs()
+            .checked_sub(self.saved_at_secs)
+            .map(Duration::from_secs)
+    }
+
+    /// Worth offering to resume. With no way to tell the age, the referee gets to decide.
+    pub fn is_recent(&self) -> bool {
+        self.age().map_or(true, |age| age <= MAX_CHECKPOINT_AGE)
+    }
+}
+
+/// A game that was cut short and can be resumed
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RecoverableGame {
+    /// How long ago it was checkpointed, when that can be told
+    pub age_secs: Option<u64>,
+    #[serde(flatten)]
+    pub game: Checkpoint,
+}
+
+impl From<&SavedGame> for RecoverableGame {
+    fn from(saved: &SavedGame) -> Self {
+        Self {
+            age_secs: saved.age().map(|age| age.as_secs()),
+            game: saved.game,
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
@@ -1,2 +1,141 @@
+use std::sync::OnceLock;
+
+use crate::{app::Team, hardware::bt::PCM_BYTES_PER_SEC};
+
+// Which clips are flashed in is picked with the `sound-*` features. Without a recorded set, or
+// with `sound-minimal`, every cue is a synthesized beep.
+
+#[cfg(all(feature = "sound-zona", not(feature = "sound-minimal")))]
 pub const RED_TEAM_CAPTURE_SOUND: &[u8] = include_bytes!("../data/zona-vermelha-dominada.sbc");
+#[cfg(all(feature = "sound-zona", not(feature = "sound-minimal")))]
 pub const BLUE_TEAM_CAPTURE_SOUND: &[u8] = include_bytes!("../data/zona-azul-dominada.sbc");
+
+// There are no dedicated victory recordings yet, the capture clips stand in for them
+#[cfg(all(feature = "sound-zona", not(feature = "sound-minimal")))]
+pub const RED_TEAM_VICTORY_SOUND: &[u8] = RED_TEAM_CAPTURE_SOUND;
+#[cfg(all(feature = "sound-zona", not(feature = "sound-minimal")))]
+pub const BLUE_TEAM_VICTORY_SOUND: &[u8] = BLUE_TEAM_CAPTURE_SOUND;
+
+#[cfg(all(feature = "sound-zona", not(feature = "sound-minimal")))]
+pub fn capture_sound(team: Team) -> &'static [u8] {
+    match team {
+        Team::Red => RED_TEAM_CAPTURE_SOUND,
+        Team::Blue => BLUE_TEAM_CAPTURE_SOUND,
+    }
+}
+
+#[cfg(all(feature = "sound-zona", not(feature = "sound-minimal")))]
+pub fn victory_sound(team: Team) -> &'static [u8] {
+    match team {
+        Team::Red => RED_TEAM_VICTORY_SOUND,
+        Team::Blue => BLUE_TEAM_VICTORY_SOUND,
+    }
+}
+
+/// Beep set: a low blip for red, a high one for blue
+#[cfg(not(all(feature = "sound-zona", not(feature = "sound-minimal"))))]
+pub fn capture_sound(team: Team) -> &'static [u8] {
+    static RED: OnceLock<Vec<u8>> = OnceLock::new();
+    static BLUE: OnceLock<Vec<u8>> = OnceLock::new();
+
+    match team {
+        Team::Red => RED.get_or_init(|| tone(600.0, 200)),
+        Team::Blue => BLUE.get_or_init(|| tone(1_200.0, 200)),
+    }
+}
+
+/// Beep set: the team's capture pitch, held longer
+#[cfg(not(all(feature = "sound-zona", not(feature = "sound-minimal"))))]
+pub fn victory_sound(team: Team) -> &'static [u8] {
+    static RED: OnceLock<Vec<u8>> = OnceLock::new();
+    static BLUE: OnceLock<Vec<u8>> = OnceLock::new();
+
+    match team {
+        Team::Red => RED.get_or_init(|| tone(600.0, 800)),
+        Team::Blue => BLUE.get_or_init(|| tone(1_200.0, 800)),
+    }
+}
+
+/// Short 1kHz tone to check the speaker with, synthesized on first use
+pub fn test_beep() -> &'static [u8] {
+    static BEEP: OnceLock<Vec<u8>> = OnceLock::new();
+
+    BEEP.get_or_init(|| tone(1_000.0, 150))
+}
+
+/// `ms` of a sine at `freq` Hz, in the clips' 16 bit 44.1kHz stereo PCM
+fn tone(freq: f32, ms: usize) -> Vec<u8> {
+    const SAMPLE_RATE: f32 = 44_100.0;
+    const AMPLITUDE: f32 = i16::MAX as f32 * 0.5;
+    let frames = PCM_BYTES_PER_SEC / 4 * ms / 1000;
+
+    let mut pcm = Vec::with_capacity(frames * 4);
+    for i in 0..frames {
+        let t = i as f32 / SAMPLE_RATE;
+        let sample = ((t * freq * std::f32::consts::TAU).sin() * AMPLITUDE) as i16;
+        // Same sample on left and right
+        pcm.extend_from_slice(&sample.to_le_bytes());
+        pcm.extend_from_slice(&sample.to_le_bytes());
+    }
+    pcm
+}
+
+/// Neutral cue for a game ending level, no recording for it yet
+pub fn draw_sound() -> &'static [u8] {
+    test_beep()
+}
+
+/// Tension cue for a team closing in on the win, no recording for it yet
+pub fn warning_sound() -> &'static [u8] {
+    test_beep()
+}
+
+/// Jingle for attract mode, no recording for it yet
+pub fn attract_sound() -> &'static [u8] {
+    test_beep()
+}
+
+/// Cue for the game moving into its next phase, no recording for it yet either
+pub fn phase_sound() -> &'static [u8] {
+    test_beep()
+}
+
+/// The PCM samples of a WAV file, which has to match the clips: 16 bit 44.1kHz stereo.
+/// Borrows from `wav`, a bundled file plays without a copy.
+pub fn wav_pcm(wav: &'static [u8]) -> anyhow::Result<&'static [u8]> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(anyhow::anyhow!("Not a WAV file"));
+    }
+
+    let u16_at = |at: usize| u16::from_le_bytes([wav[at], wav[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes([wav[at], wav[at + 1], wav[at + 2], wav[at + 3]]);
+
+    let mut format_ok = None;
+    let mut at = 12;
+    while at + 8 <= wav.len() {
+        let id = &wav[at..at + 4];
+        let len = u32_at(at + 4) as usize;
+        let body = at + 8;
+        let end = body.saturating_add(len).min(wav.len());
+        match id {
+            b"fmt " if end - body >= 16 => {
+                let pcm = u16_at(body) == 1;
+                let channels = u16_at(body + 2);
+                let rate = u32_at(body + 4);
+                let bits = u16_at(body + 14);
+                format_ok = Some(pcm && channels == 2 && rate == 44_100 && bits == 16);
+            }
+            b"data" => {
+                return match format_ok {
+                    Some(true) => Ok(&wav[body..end]),
+                    Some(false) => Err(anyhow::anyhow!("Only 16 bit 44.1kHz stereo PCM plays")),
+                    None => Err(anyhow::anyhow!("WAV file has no format before its data")),
+                };
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at = end + (len & 1);
+    }
+    Err(anyhow::anyhow!("WAV file has no data"))
+}
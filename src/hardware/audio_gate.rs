@@ -0,0 +1,198 @@
+//! Generation gating between the Bluetooth audio task and the A2DP `SourceData` callback, see
+//! the concurrency contract in `bt`. Works on any byte queue, nothing in here needs the IDF,
+//! so it builds and is tested on the host.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub struct AudioGate {
+    /// Bumped by every play, stream and stop
+    requested: AtomicU32,
+    /// Generation of the clip currently held in the ring buffer
+    buffered: AtomicU32,
+}
+
+impl Default for AudioGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioGate {
+    pub const fn new() -> Self {
+        Self {
+            requested: AtomicU32::new(0),
+            buffered: AtomicU32::new(0),
+        }
+    }
+
+    /// A new sound was asked for, whatever is buffered goes stale. Returns its generation.
+    pub fn request(&self) -> u32 {
+        self.requested.fetch_add(1, Ordering::SeqCst).wrapping_add(1)
+    }
+
+    pub fn current(&self) -> u32 {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Whether `gen` is still the sound asked for last
+    pub fn is_current(&self, gen: u32) -> bool {
+        self.current() == gen
+    }
+
+    /// Audio task, before writing a sound: `flush` the buffer, only then mark it as holding
+    /// the current generation. Returns that generation.
+    pub fn claim(&self, flush: impl FnOnce()) -> u32 {
+        let gen = self.current();
+        flush();
+        self.buffered.store(gen, Ordering::SeqCst);
+        gen
+    }
+
+    /// `SourceData`: fill `buffer` through `receive` while the buffered audio is current,
+    /// silence otherwise. Returns how many bytes are good to send.
+    pub fn fill(
+        &self,
+        buffer: &mut [u8],
+        muted: bool,
+        receive: impl FnOnce(&mut [u8]) -> usize,
+    ) -> usize {
+        // Whatever is buffered belongs to a clip that was cut, don't emit it
+        if self.buffered.load(Ordering::SeqCst) != self.current() {
+            buffer.fill(0);
+            return buffer.len();
+        }
+
+        let copied = receive(buffer);
+        if copied == 0 {
+            // Ring buffer empty: fill with silence (zeros) to avoid BT stall
+            buffer.fill(0);
+            return buffer.len();
+        }
+        // Muted clips still drain at the normal pace, they're just not heard
+        if muted {
+            buffer[..copied].fill(0);
+        }
+
+        copied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Stands in for the IDF ring buffer
+    #[derive(Default)]
+    struct FakeRing(VecDeque<u8>);
+
+    impl FakeRing {
+        fn send(&mut self, data: &[u8]) {
+            self.0.extend(data);
+        }
+
+        fn receive_into(&mut self, buf: &mut [u8]) -> usize {
+            let len = buf.len().min(self.0.len());
+            for (slot, byte) in buf.iter_mut().zip(self.0.drain(..len)) {
+                *slot = byte;
+            }
+            len
+        }
+    }
+
+    /// What one `SourceData` callback asking for `len` bytes sends
+    fn source(gate: &AudioGate, ring: &mut FakeRing, len: usize, muted: bool) -> Vec<u8> {
+        let mut buffer = vec![0xee; len];
+        let sent = gate.fill(&mut buffer, muted, |buf| ring.receive_into(buf));
+        buffer.truncate(sent);
+        buffer
+    }
+
+    /// Audio task starting a sound
+    fn play(gate: &AudioGate, ring: &mut FakeRing, clip: &[u8]) {
+        gate.request();
+        gate.claim(|| ring.0.clear());
+        ring.send(clip);
+    }
+
+    #[test]
+    fn current_clip_plays_through() {
+        let gate = AudioGate::new();
+        let mut ring = FakeRing::default();
+        play(&gate, &mut ring, &[1, 2, 3, 4]);
+
+        assert_eq!(source(&gate, &mut ring, 3, false), [1, 2, 3]);
+        assert_eq!(source(&gate, &mut ring, 3, false), [4]);
+    }
+
+    #[test]
+    fn empty_buffer_sends_silence() {
+        let gate = AudioGate::new();
+        let mut ring = FakeRing::default();
+        play(&gate, &mut ring, &[]);
+
+        assert_eq!(source(&gate, &mut ring, 4, false), [0; 4]);
+    }
+
+    #[test]
+    fn cut_clip_is_never_heard() {
+        let gate = AudioGate::new();
+        let mut ring = FakeRing::default();
+        play(&gate, &mut ring, &[1, 2, 3, 4]);
+
+        // A new sound was asked for, the audio task hasn't got to it yet
+        gate.request();
+        assert_eq!(source(&gate, &mut ring, 2, false), [0, 0]);
+        // Left for the audio task to flush rather than drained
+        assert_eq!(ring.0.len(), 4);
+    }
+
+    #[test]
+    fn new_clip_starts_with_its_head() {
+        let gate = AudioGate::new();
+        let mut ring = FakeRing::default();
+        play(&gate, &mut ring, &[1, 2, 3, 4]);
+
+        let gen = gate.request();
+        assert_eq!(source(&gate, &mut ring, 2, false), [0, 0]);
+        assert_eq!(gate.claim(|| ring.0.clear()), gen);
+        // Between the claim and the first write the buffer is current but empty
+        assert_eq!(source(&gate, &mut ring, 2, false), [0, 0]);
+        ring.send(&[5, 6, 7]);
+        assert_eq!(source(&gate, &mut ring, 2, false), [5, 6]);
+    }
+
+    #[test]
+    fn stop_silences_what_is_left() {
+        let gate = AudioGate::new();
+        let mut ring = FakeRing::default();
+        play(&gate, &mut ring, &[1, 2, 3, 4]);
+
+        let gen = gate.request();
+        assert!(gate.is_current(gen));
+        assert_eq!(source(&gate, &mut ring, 4, false), [0; 4]);
+        // Stopping flushes without claiming, the buffer stays silenced until the next sound
+        ring.0.clear();
+        ring.send(&[5]);
+        assert_eq!(source(&gate, &mut ring, 4, false), [0; 4]);
+    }
+
+    #[test]
+    fn muted_clip_drains_as_silence() {
+        let gate = AudioGate::new();
+        let mut ring = FakeRing::default();
+        play(&gate, &mut ring, &[1, 2, 3, 4]);
+
+        assert_eq!(source(&gate, &mut ring, 3, true), [0, 0, 0]);
+        assert_eq!(source(&gate, &mut ring, 3, false), [4]);
+    }
+
+    #[test]
+    fn older_generation_is_not_current() {
+        let gate = AudioGate::new();
+        let gen = gate.request();
+        gate.request();
+        assert!(!gate.is_current(gen));
+    }
+}
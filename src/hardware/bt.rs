@@ -1,3 +1,15 @@
+//! Bluetooth A2DP source streaming the capture clips to a speaker.
+//!
+//! Concurrency contract:
+//! - The audio task is the only writer of the ring buffer (`send_bytes`) and the only one
+//!   flushing it on a clip change. Live streams go through it as well (`AudioStream`).
+//! - The BT stack's `SourceData` callback is the only reader during playback.
+//! - Every `play_audio`/stop bumps the generation in `AUDIO_GATE`. The audio task flushes and
+//!   then claims the buffer for the generation it is about to write (`AudioGate::claim`).
+//!   `SourceData` only drains the ring buffer while both match, otherwise it emits silence,
+//!   so the tail of a cut clip is never heard and a racing flush can't drop the head of the
+//!   new one. `audio_gate` has host tests of this over a fake ring buffer.
+
 use std::fmt::Debug;
 use std::result::Result::Ok;
 use std::sync::Arc;
@@ -5,66 +17,279 @@ use std::{
     fmt::Display,
     sync::{
         atomic::AtomicBool,
-        mpsc::{Receiver, Sender},
-        RwLock,
+        mpsc::{Receiver, RecvTimeoutError, Sender, SyncSender},
+        Mutex, OnceLock, RwLock, Weak,
     },
 };
 
 use anyhow::Result;
+use crate::app::AudioSink;
+use crate::hardware::{audio_gate::AudioGate, ringbuf::RingBuffer};
+use serde::{Deserialize, Serialize};
 use esp_idf_svc::{
     bt::{
-        a2dp::{A2dpEvent, ConnectionStatus, EspA2dp, Source},
+        a2dp::{A2dpEvent, Codec, ConnectionStatus, EspA2dp, Source},
         avrc::controller::{AvrccEvent, EspAvrcc},
-        gap::{EspGap, InqMode},
+        gap::{CodMajorDeviceType, EspGap, InqMode},
         BdAddr, BtClassic, BtDriver,
     },
     hal::{modem::BluetoothModemPeripheral, peripheral::Peripheral},
     nvs::EspDefaultNvsPartition,
     sys::{
-        esp_a2d_media_ctrl, esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_START, vRingbufferReturnItem,
-        xRingbufferCreate, xRingbufferReceiveUpTo, xRingbufferSend, RingbufHandle_t,
-        RingbufferType_t_RINGBUF_TYPE_BYTEBUF,
+        esp, esp_a2d_media_ctrl, esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_START,
+        esp_bd_addr_t, esp_bt_gap_get_bond_device_list, esp_bt_gap_get_bond_device_num,
+        esp_bt_gap_read_remote_name, esp_bt_gap_remove_bond_device,
+        esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_SUSPEND,
     },
+    timer::EspTaskTimerService,
 };
 
+/// The clips are 44.1kHz, 16 bit, stereo PCM: 44100 * 2 * 2 = 176400 bytes per second.
+/// Whatever sits in the ring buffer is heard that much later, so
+/// 64KB of backlog is ~370ms of lag, 16KB is ~93ms and 4KB is ~23ms.
+pub const PCM_BYTES_PER_SEC: usize = 44_100 * 2 * 2;
+
+/// Ring buffer size used when the caller has no specific needs (~93ms of audio)
+pub const DEFAULT_RING_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Wait for a speaker to answer one remote name request
+const NAME_REQUEST_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Most a scan is stretched by asking nameless devices for their names
+const NAME_RESOLVE_BUDGET: Duration = Duration::from_secs(5);
+
+/// Extra remote name requests to a device that didn't answer the first one
+const DEFAULT_NAME_RETRIES: usize = 1;
+
+/// Smallest ring buffer we fall back to when the heap is short, still fits the default
+/// `AudioConfig` prefill
+const MIN_RING_BUFFER_SIZE: usize = 4 * 1024;
+
+// Measuring end-to-end delay: wire a spare GPIO high right before `play_audio` and put a
+// scope/logic analyzer on it and on the speaker's output (or a mic next to it). The gap is
+// button ISR -> app loop (<=10ms) + BT stack/SBC encode (~20-40ms, speaker dependent) +
+// `buffered_bytes()` worth of ring buffer at the moment the clip's head is queued.
+// With the flush before prefill the last term is just the prefill.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtError {
+    /// Another discovery or connection is already under way
+    Busy,
+    /// An `AudioConfig` the ring buffer can't work with
+    InvalidAudioConfig(&'static str),
+}
+
+impl Display for BtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BtError::Busy => f.write_str("Another Bluetooth operation is in progress"),
+            BtError::InvalidAudioConfig(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for BtError {}
+
+/// Held for the length of a discovery or connection, frees the stack up again when dropped
+struct BusyGuard<'a>(&'a AtomicBool);
+
+impl<'a> BusyGuard<'a> {
+    fn acquire(busy: &'a AtomicBool) -> Result<Self, BtError> {
+        busy.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .map_err(|_| BtError::Busy)?;
+        Ok(Self(busy))
+    }
+}
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// How the audio task paces the ring buffer. Speakers differ in how they take it,
+/// these are the knobs for one that underruns or lags.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AudioConfig {
+    /// Bytes written to the ring buffer at a time
+    pub chunk: usize,
+    /// Bytes of a clip queued in one go before the rest is paced in chunks
+    pub prefill: usize,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            chunk: 512,
+            prefill: 4096,
+        }
+    }
+}
+
+impl AudioConfig {
+    fn validate(&self, ring_buf_size: usize) -> Result<(), BtError> {
+        if self.chunk == 0 {
+            return Err(BtError::InvalidAudioConfig("chunk must be above zero"));
+        }
+        if self.chunk > ring_buf_size || self.prefill > ring_buf_size {
+            return Err(BtError::InvalidAudioConfig(
+                "chunk and prefill must fit in the ring buffer",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Stops a running discovery when dropped, e.g. with the future that started it
+struct DiscoveryGuard<'a>(&'a BluetoothAudio);
+
+impl DiscoveryGuard<'_> {
+    /// Stop now, reporting failures the drop would only log
+    fn finish(self) -> Result<()> {
+        // Dropping afterwards is a no-op, discovery is no longer running
+        self.0.stop_discovery()
+    }
+}
+
+impl Drop for DiscoveryGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.stop_discovery() {
+            log::warn!(target: "bt", "Failed to stop an abandoned discovery: {err}");
+        }
+    }
+}
+
+/// Name request in flight, see `NameRequests`
+struct NameRequest {
+    addr: BdAddr,
+    attempts_left: usize,
+    deadline: Instant,
+}
+
+/// Asks the devices a scan is about to return that showed up without a name for it, one at a
+/// time. Speakers often leave it out of the inquiry response. Takes at most
+/// `NAME_RESOLVE_BUDGET`, a device that never answers just stays nameless.
+/// The blocking and the async scan step through the same requests and only wait differently.
+struct NameRequests {
+    budget_end: Instant,
+    nameless: std::vec::IntoIter<BdAddr>,
+    current: Option<NameRequest>,
+}
+
+impl NameRequests {
+    fn new(bt: &BluetoothAudio, max: usize, filter: fn(&BtDevice) -> bool) -> Self {
+        Self {
+            budget_end: Instant::now() + NAME_RESOLVE_BUDGET,
+            nameless: bt.nameless(max, filter).into_iter(),
+            current: None,
+        }
+    }
+
+    /// Send whatever request is due, then how long to wait before calling again.
+    /// `None` once every device answered, ran out of attempts or the budget is spent.
+    fn next_wait(&mut self, bt: &BluetoothAudio) -> Option<Duration> {
+        loop {
+            let now = Instant::now();
+            if now >= self.budget_end {
+                return None;
+            }
+            let Some(request) = &mut self.current else {
+                let addr = self.nameless.next()?;
+                self.current = Some(NameRequest {
+                    addr,
+                    attempts_left: bt.name_attempts(),
+                    deadline: now,
+                });
+                continue;
+            };
+            if bt.is_named(request.addr) {
+                self.current = None;
+                continue;
+            }
+            if now < request.deadline {
+                return Some(Duration::from_millis(50));
+            }
+            if request.attempts_left == 0 || !bt.request_name(request.addr) {
+                self.current = None;
+                continue;
+            }
+            request.attempts_left -= 1;
+            request.deadline = (now + NAME_REQUEST_TIMEOUT).min(self.budget_end);
+            return Some(Duration::from_millis(50));
+        }
+    }
+}
+
 type BtClassicDriver = BtDriver<'static, BtClassic>;
 type EspBtClassicGap = EspGap<'static, BtClassic, Arc<BtClassicDriver>>;
 
 enum AudioCommand {
     Play(&'static [u8]),
+    /// Live audio, fed until the sending side hangs up, see `AudioStream`
+    Stream(Receiver<Vec<u8>>),
     Stop,
 }
 
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-static AUDIO_GEN: AtomicU32 = AtomicU32::new(0);
+static AUDIO_GATE: AudioGate = AudioGate::new();
+
+/// Flags the audio task as dead when it exits, even by panicking
+struct AudioTaskGuard(Arc<BluetoothAudio>);
+
+impl Drop for AudioTaskGuard {
+    fn drop(&mut self) {
+        self.0.task_alive.store(false, Ordering::SeqCst);
+        log::warn!(target: "bt", "Audio task exited");
+    }
+}
 
 fn spawn_audio_task(bt: Arc<BluetoothAudio>, rx: Receiver<AudioCommand>) {
+    bt.task_alive.store(true, Ordering::SeqCst);
     std::thread::spawn(move || {
-        const CHUNK: usize = 512;
-        const PREFILL: usize = 4096;
+        let _guard = AudioTaskGuard(bt.clone());
 
         loop {
-            match rx.recv() {
+            let cmd = rx.recv();
+            // Picked up fresh for every command, so a change applies from the next clip
+            let tuning = bt.audio_config();
+
+            match cmd {
                 Ok(AudioCommand::Play(data)) => {
-                    let my_gen = AUDIO_GEN.load(Ordering::SeqCst);
-                    // Hard cut: flush anything pending
-                    bt.flush_ringbuffer();
+                    // Paused while the device is in low-power mode
+                    while !bt.awake.load(Ordering::Relaxed) {
+                        std::thread::sleep(std::time::Duration::from_millis(100));
+                    }
+
+                    // Hard cut: flush anything pending, only then claim the buffer for this clip
+                    let my_gen = AUDIO_GATE.claim(|| bt.flush_ringbuffer());
+
+                    let target = bt.latency_target.load(Ordering::Relaxed);
+                    bt.set_playback_status(PlaybackStatus::Playing);
 
                     // ---- PREFILL ----
-                    let prefill = PREFILL.min(data.len());
+                    // Never prefill past the latency target, or the clip's head sits behind it
+                    let prefill = tuning.prefill.min(target).min(data.len());
                     bt.send_bytes(&data[..prefill], esp_idf_svc::sys::TickType_t::MAX);
 
                     let mut offset = prefill;
 
                     // ---- STREAM ----
                     while offset < data.len() {
-                        // If a newer Play() happened → exit immediately
-                        if AUDIO_GEN.load(Ordering::Relaxed) != my_gen {
+                        // If a newer Play() happened or we went to sleep → exit immediately
+                        if !AUDIO_GATE.is_current(my_gen) || !bt.awake.load(Ordering::Relaxed)
+                        {
                             break;
                         }
 
-                        let end = (offset + CHUNK).min(data.len());
+                        // Keep the backlog under the latency target
+                        if bt.buffered_bytes() + tuning.chunk > target {
+                            std::thread::sleep(std::time::Duration::from_millis(2));
+                            continue;
+                        }
+
+                        let end = (offset + tuning.chunk).min(data.len());
 
                         bt.send_bytes(&data[offset..end], esp_idf_svc::sys::TickType_t::MAX);
 
@@ -73,11 +298,53 @@ fn spawn_audio_task(bt: Arc<BluetoothAudio>, rx: Receiver<AudioCommand>) {
                         // Small delay to avoid BT starvation
                         std::thread::sleep(std::time::Duration::from_millis(2));
                     }
+
+                    finish_playback(&bt, my_gen);
+                }
+
+                Ok(AudioCommand::Stream(chunks)) => {
+                    while !bt.awake.load(Ordering::Relaxed) {
+                        std::thread::sleep(std::time::Duration::from_millis(100));
+                    }
+
+                    let my_gen = AUDIO_GATE.claim(|| bt.flush_ringbuffer());
+
+                    let target = bt.latency_target.load(Ordering::Relaxed);
+                    bt.set_playback_status(PlaybackStatus::Playing);
+                    let cut =
+                        || !AUDIO_GATE.is_current(my_gen) || !bt.awake.load(Ordering::Relaxed);
+
+                    // Dropping `chunks` on the way out tells the sender it was cut off
+                    'stream: loop {
+                        // Don't block on the uploader for long, a clip may want the speaker
+                        let chunk = match chunks.recv_timeout(Duration::from_millis(100)) {
+                            Ok(chunk) => chunk,
+                            Err(RecvTimeoutError::Timeout) if !cut() => continue,
+                            Err(_) => break,
+                        };
+
+                        for piece in chunk.chunks(tuning.chunk) {
+                            // Holding off here backs the upload up through the channel
+                            while bt.buffered_bytes() + piece.len() > target {
+                                if cut() {
+                                    break 'stream;
+                                }
+                                std::thread::sleep(std::time::Duration::from_millis(2));
+                            }
+                            if cut() {
+                                break 'stream;
+                            }
+                            bt.send_bytes(piece, esp_idf_svc::sys::TickType_t::MAX);
+                        }
+                    }
+
+                    finish_playback(&bt, my_gen);
                 }
 
                 Ok(AudioCommand::Stop) => {
-                    AUDIO_GEN.fetch_add(1, Ordering::SeqCst);
+                    AUDIO_GATE.request();
                     bt.flush_ringbuffer();
+                    bt.set_playback_status(PlaybackStatus::Stopped);
                 }
 
                 Err(_) => break,
@@ -86,10 +353,132 @@ fn spawn_audio_task(bt: Arc<BluetoothAudio>, rx: Receiver<AudioCommand>) {
     });
 }
 
-#[derive(Debug, Clone)]
+/// Live PCM (same format as the clips) on its way to the speaker, e.g. a referee mic.
+/// Starting one cuts whatever is playing, and the next clip played cuts it in turn.
+pub struct AudioStream {
+    tx: SyncSender<Vec<u8>>,
+    gen: u32,
+}
+
+impl AudioStream {
+    /// Queue the next piece, blocking while the speaker is behind.
+    /// `false` once the stream was cut off, nothing more will be played then.
+    pub fn write(&self, pcm: &[u8]) -> bool {
+        if !AUDIO_GATE.is_current(self.gen) {
+            return false;
+        }
+        self.tx.send(pcm.to_vec()).is_ok()
+    }
+}
+
+/// Once the ring buffer has drained tell the speaker playback stopped,
+/// unless a newer clip took over in the meantime
+fn finish_playback(bt: &BluetoothAudio, gen: u32) {
+    while bt.buffered_bytes() > 0 && AUDIO_GATE.is_current(gen) {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    if AUDIO_GATE.is_current(gen) {
+        bt.set_playback_status(PlaybackStatus::Stopped);
+    }
+}
+
+/// What the speaker is told about playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackStatus {
+    Playing,
+    Stopped,
+}
+
+/// (De)serializes a `BdAddr` as `"AA:BB:CC:DD:EE:FF"`
+pub mod bd_addr_str {
+    use esp_idf_svc::bt::BdAddr;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn format(addr: &BdAddr) -> String {
+        addr.addr()
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<BdAddr> {
+        let mut bytes = [0u8; 6];
+        let mut parts = s.trim().split(':');
+        for byte in bytes.iter_mut() {
+            let part = parts
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Address '{s}' is too short"))?;
+            if part.len() != 2 {
+                return Err(anyhow::anyhow!("Invalid byte '{part}' in address '{s}'"));
+            }
+            *byte = u8::from_str_radix(part, 16)?;
+        }
+        if parts.next().is_some() {
+            return Err(anyhow::anyhow!("Address '{s}' is too long"));
+        }
+
+        Ok(BdAddr::from_bytes(bytes))
+    }
+
+    pub fn serialize<S: Serializer>(addr: &BdAddr, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format(addr))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BdAddr, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse(&s).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BtDevice {
     name: Option<Arc<String>>,
+    #[serde(with = "bd_addr_str")]
     addr: BdAddr,
+    /// Class of Device says it's an audio/video device
+    #[serde(default)]
+    audio_sink: bool,
+    /// Last inquiry response from it
+    #[serde(rename = "seen_secs_ago", serialize_with = "secs_ago", skip_deserializing)]
+    last_seen: Option<Instant>,
+}
+
+fn secs_ago<S: serde::Serializer>(at: &Option<Instant>, serializer: S) -> Result<S::Ok, S::Error> {
+    match at {
+        Some(at) => serializer.serialize_some(&at.elapsed().as_secs()),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl BtDevice {
+    /// A device we only know the address of
+    pub fn from_addr(addr: BdAddr) -> Self {
+        Self {
+            name: None,
+            addr,
+            audio_sink: false,
+            last_seen: None,
+        }
+    }
+
+    pub fn is_audio_sink(&self) -> bool {
+        self.audio_sink
+    }
+
+    pub fn addr(&self) -> BdAddr {
+        self.addr
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref().map(|name| name.as_str())
+    }
+
+    /// Whether it answered an inquiry within `ttl`
+    pub fn seen_within(&self, ttl: Duration) -> bool {
+        self.last_seen.is_some_and(|seen| seen.elapsed() <= ttl)
+    }
 }
 
 impl Display for BtDevice {
@@ -101,7 +490,7 @@ impl Display for BtDevice {
                 "Unknown"
             }
         };
-        write!(f, "{} at {}", name, self.addr.to_string())
+        write!(f, "{} at {}", name, bd_addr_str::format(&self.addr))
     }
 }
 
@@ -113,12 +502,91 @@ impl PartialEq for BtDevice {
 
 impl Eq for BtDevice {}
 
-#[derive(Copy, Clone)]
-struct Ringbuf(RingbufHandle_t);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMode {
+    Mono,
+    DualChannel,
+    Stereo,
+    JointStereo,
+}
+
+/// Media parameters negotiated with the sink
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioFormat {
+    pub codec: &'static str,
+    pub sample_rate: Option<u32>,
+    pub channel_mode: Option<ChannelMode>,
+}
+
+impl AudioFormat {
+    fn from_codec(codec: &Codec) -> Self {
+        match codec {
+            // First byte of the SBC codec info element: sampling frequency | channel mode
+            Codec::Sbc(info) => Self {
+                codec: "sbc",
+                sample_rate: match info[0] & 0xF0 {
+                    0x80 => Some(16_000),
+                    0x40 => Some(32_000),
+                    0x20 => Some(44_100),
+                    0x10 => Some(48_000),
+                    _ => None,
+                },
+                channel_mode: match info[0] & 0x0F {
+                    0x08 => Some(ChannelMode::Mono),
+                    0x04 => Some(ChannelMode::DualChannel),
+                    0x02 => Some(ChannelMode::Stereo),
+                    0x01 => Some(ChannelMode::JointStereo),
+                    _ => None,
+                },
+            },
+            Codec::Mpeg1_2(_) => Self::unparsed("mpeg1_2"),
+            Codec::Mpeg2_4(_) => Self::unparsed("mpeg2_4"),
+            Codec::Atrac(_) => Self::unparsed("atrac"),
+            _ => Self::unparsed("unknown"),
+        }
+    }
+
+    fn unparsed(codec: &'static str) -> Self {
+        Self {
+            codec,
+            sample_rate: None,
+            channel_mode: None,
+        }
+    }
+
+    /// Whether this matches what the clips are encoded as (44.1kHz stereo)
+    pub fn matches_clips(&self) -> bool {
+        self.sample_rate == Some(44_100)
+            && matches!(
+                self.channel_mode,
+                Some(ChannelMode::Stereo | ChannelMode::JointStereo)
+            )
+    }
+}
 
-// ESP-IDF ring buffers are thread-safe by design
-unsafe impl Send for Ringbuf {}
-unsafe impl Sync for Ringbuf {}
+/// Allocate a `size` byte ring buffer, halving down to `MIN_RING_BUFFER_SIZE` if the heap
+/// can't spare that much. A smaller buffer only means less slack for jitter.
+fn create_ring_buffer(size: usize) -> anyhow::Result<RingBuffer> {
+    let mut attempt = size;
+    loop {
+        if let Ok(ring_buf) = RingBuffer::new(attempt) {
+            if attempt < size {
+                log::warn!(
+                    target: "bt",
+                    "No heap for a {size} byte ring buffer, using {attempt} bytes"
+                );
+            }
+            return Ok(ring_buf);
+        }
+        if attempt <= MIN_RING_BUFFER_SIZE {
+            return Err(anyhow::anyhow!(
+                "Failed to allocate an audio ring buffer of {attempt} bytes or more"
+            ));
+        }
+        attempt = (attempt / 2).max(MIN_RING_BUFFER_SIZE);
+    }
+}
 
 #[allow(dead_code)]
 pub struct BluetoothAudio {
@@ -127,10 +595,50 @@ pub struct BluetoothAudio {
     gap: EspBtClassicGap,
     discovered_devices: Arc<RwLock<Vec<BtDevice>>>,
     is_in_discovery: AtomicBool,
+    /// Set while a discovery or connection owns the GAP/A2DP side, see `BusyGuard`
+    busy: AtomicBool,
+    awake: AtomicBool,
     a2dp: EspA2dp<'static, BtClassic, Arc<BtClassicDriver>, Source>,
     avrc: Arc<EspAvrcc<'static, BtClassic, Arc<BtClassicDriver>>>,
-    ring_buf: Arc<Ringbuf>,
-    audio_cmd_tx: Sender<AudioCommand>,
+    ring_buf: RingBuffer,
+    latency_target: AtomicUsize,
+    audio_cmd_tx: Mutex<Sender<AudioCommand>>,
+    task_alive: AtomicBool,
+    sink_connected: AtomicBool,
+    audio_format: RwLock<Option<AudioFormat>>,
+    audio_config: RwLock<AudioConfig>,
+    playing: AtomicBool,
+    /// Suspend the A2DP stream between clips, see `set_suspend_when_idle`
+    suspend_when_idle: AtomicBool,
+    media_suspended: AtomicBool,
+    /// Send silence in place of whatever plays, see `set_muted`
+    muted: AtomicBool,
+    /// See `set_name_retries`
+    name_retries: AtomicUsize,
+    /// Handle on our own `Arc`, to restart the audio task from `&self`
+    me: OnceLock<Weak<Self>>,
+}
+
+impl AudioSink for BluetoothAudio {
+    fn play(&self, clip: &'static [u8]) {
+        self.play_audio(clip);
+    }
+
+    fn stop(&self) {
+        self.stop_audio();
+    }
+
+    fn is_connected(&self) -> bool {
+        BluetoothAudio::is_connected(self)
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.audio_healthy()
+    }
+
+    fn set_awake(&self, awake: bool) {
+        BluetoothAudio::set_awake(self, awake);
+    }
 }
 
 impl Debug for BluetoothAudio {
@@ -143,10 +651,12 @@ impl BluetoothAudio {
     pub fn init<B: BluetoothModemPeripheral>(
         modem: impl Peripheral<P = B> + 'static,
         nvs: Option<EspDefaultNvsPartition>,
+        ring_buf_size: usize,
     ) -> anyhow::Result<Arc<Self>> {
         let (tx, rx) = std::sync::mpsc::channel();
-        let bt = Arc::new(BluetoothAudio::new(modem, nvs, tx)?);
-        log::info!("Init Bluetooth Audio");
+        let bt = Arc::new(BluetoothAudio::new(modem, nvs, ring_buf_size, tx)?);
+        bt.me.set(Arc::downgrade(&bt)).ok();
+        log::info!(target: "bt", "Init Bluetooth Audio");
         spawn_audio_task(bt.clone(), rx);
         let a2dp_bt = bt.clone();
         let avrc_bt = bt.clone();
@@ -162,31 +672,52 @@ impl BluetoothAudio {
     fn new<B: BluetoothModemPeripheral>(
         modem: impl Peripheral<P = B> + 'static,
         nvs: Option<EspDefaultNvsPartition>,
+        ring_buf_size: usize,
         tx: Sender<AudioCommand>,
     ) -> Result<Self> {
+        // Bluedroid keeps link keys in NVS, without the partition every boot means re-pairing
+        if nvs.is_none() {
+            log::warn!(
+                target: "bt",
+                "No NVS for Bluetooth, speakers will have to pair again after a reboot"
+            );
+        }
         let driver = Arc::new(BtDriver::new(modem, nvs)?);
         driver.set_device_name("Esp32dominacao")?;
         let gap = EspGap::new(driver.clone())?;
         gap.request_variable_pin()?;
-        let handle = unsafe { xRingbufferCreate(64 * 1024, RingbufferType_t_RINGBUF_TYPE_BYTEBUF) };
+        let ring_buf = create_ring_buffer(ring_buf_size)?;
         let avrc = EspAvrcc::new(driver.clone())?;
         let a2dp = EspA2dp::new_source(driver.clone())?;
 
         Ok(Self {
             connection: RwLock::new(None),
-            audio_cmd_tx: tx,
+            audio_cmd_tx: Mutex::new(tx),
+            task_alive: false.into(),
+            sink_connected: false.into(),
+            audio_format: RwLock::new(None),
+            audio_config: RwLock::new(AudioConfig::default()),
+            playing: false.into(),
+            suspend_when_idle: true.into(),
+            muted: false.into(),
+            name_retries: AtomicUsize::new(DEFAULT_NAME_RETRIES),
+            media_suspended: false.into(),
+            me: OnceLock::new(),
             gap,
             driver: driver.clone(),
             discovered_devices: Arc::new(RwLock::new(vec![])),
             is_in_discovery: false.into(),
+            busy: false.into(),
+            awake: true.into(),
             a2dp,
             avrc: Arc::new(avrc),
-            ring_buf: Arc::new(Ringbuf(handle)),
+            latency_target: AtomicUsize::new(ring_buf.size()),
+            ring_buf,
         })
     }
 
     fn avrc_event_handler(bt: Arc<Self>, ev: AvrccEvent) {
-        log::info!("{:#?}", ev);
+        log::info!(target: "bt", "{:#?}", ev);
     }
 
     fn a2dp_event_handler(bt: Arc<Self>, ev: A2dpEvent) -> usize {
@@ -196,77 +727,218 @@ impl BluetoothAudio {
                 status,
                 disconnect_abnormal: _,
             } => {
-                if status == ConnectionStatus::Connected {
-                    unsafe { esp_a2d_media_ctrl(esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_START) };
-                    log::info!("Started media on {bd_addr}");
+                match status {
+                    ConnectionStatus::Connected => {
+                        bt.sink_connected.store(true, Ordering::SeqCst);
+                        unsafe { esp_a2d_media_ctrl(esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_START) };
+                        bt.media_suspended.store(false, Ordering::SeqCst);
+                        log::info!(target: "bt", "Started media on {bd_addr}");
+                    }
+                    ConnectionStatus::Disconnected => {
+                        bt.sink_connected.store(false, Ordering::SeqCst);
+                        *bt.audio_format.write().unwrap() = None;
+                        *bt.connection.write().unwrap() = None;
+                        log::info!(target: "bt", "Disconnected from {bd_addr}");
+                    }
+                    _ => {}
                 }
                 1
             }
-            esp_idf_svc::bt::a2dp::A2dpEvent::SourceData(buffer) => {
-                let mut copied = 0;
-
-                unsafe {
-                    let mut size = 0;
-                    let item = xRingbufferReceiveUpTo(
-                        bt.ring_buf.0,
-                        &mut size,
-                        0, // no block
-                        buffer.len(),
+            esp_idf_svc::bt::a2dp::A2dpEvent::AudioSourceConfigured { bd_addr, codec, .. } => {
+                let format = AudioFormat::from_codec(&codec);
+                if format.matches_clips() {
+                    log::info!(target: "bt", "{bd_addr} negotiated {format:?}");
+                } else {
+                    log::warn!(
+                        target: "bt",
+                        "{bd_addr} negotiated {format:?}, clips are 44.1kHz stereo and will play wrong"
                     );
-
-                    if !item.is_null() {
-                        core::ptr::copy_nonoverlapping(
-                            item as *const u8,
-                            buffer.as_mut_ptr(),
-                            size,
-                        );
-                        vRingbufferReturnItem(bt.ring_buf.0, item);
-                        copied = size;
-                    } else {
-                        // Ring buffer empty: fill with silence (zeros) to avoid BT stall
-                        core::ptr::write_bytes(buffer.as_mut_ptr(), 0, buffer.len());
-                        copied = buffer.len();
-                    }
                 }
-
-                copied
+                *bt.audio_format.write().unwrap() = Some(format);
+                1
+            }
+            esp_idf_svc::bt::a2dp::A2dpEvent::SourceData(buffer) => {
+                let muted = bt.muted.load(Ordering::Relaxed);
+                AUDIO_GATE.fill(buffer, muted, |buf| bt.ring_buf.receive_into(buf))
             }
             any => {
-                log::info!("{any:?}");
+                log::info!(target: "bt", "{any:?}");
                 1
             }
         }
     }
 
     pub fn send_bytes(&self, pcm: &[u8], tick_wait: u32) {
-        unsafe {
-            xRingbufferSend(
-                self.ring_buf.0,
-                pcm.as_ptr() as *const _,
-                pcm.len(),
-                tick_wait,
-            );
-        }
+        self.ring_buf.send(pcm, tick_wait);
+    }
+    /// Bytes queued in the ring buffer but not yet handed to the A2DP stack
+    pub fn buffered_bytes(&self) -> usize {
+        self.ring_buf.len()
     }
+
+    /// Cap how much audio may be queued ahead of the speaker.
+    /// Clamped between one chunk and the whole ring buffer.
+    pub fn set_latency_target(&self, latency: Duration) {
+        let ms = usize::try_from(latency.as_millis()).unwrap_or(usize::MAX);
+        let bytes = ms.saturating_mul(PCM_BYTES_PER_SEC) / 1000;
+        let bytes = bytes.clamp(512, self.ring_buf.size());
+        self.latency_target.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn latency_target(&self) -> Duration {
+        let bytes = self.latency_target.load(Ordering::Relaxed);
+        Duration::from_millis((bytes * 1000 / PCM_BYTES_PER_SEC) as u64)
+    }
+
     fn flush_ringbuffer(&self) {
-        unsafe {
-            let mut size = 0;
-            loop {
-                let item = xRingbufferReceiveUpTo(self.ring_buf.0, &mut size, 0, usize::MAX);
-                if item.is_null() {
-                    break;
-                }
-                vRingbufferReturnItem(self.ring_buf.0, item);
+        self.ring_buf.clear();
+    }
+
+    pub fn play_audio(&self, data: &'static [u8]) {
+        AUDIO_GATE.request();
+        self.send_audio_command(AudioCommand::Play(data));
+    }
+
+    /// Take the speaker over for live audio, see `AudioStream`
+    pub fn stream_audio(&self) -> AudioStream {
+        let gen = AUDIO_GATE.request();
+        // One piece in flight, so the uploader is held back as soon as the speaker is
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        self.send_audio_command(AudioCommand::Stream(rx));
+        AudioStream { tx, gen }
+    }
+
+    pub fn stop_audio(&self) {
+        AUDIO_GATE.request();
+        self.send_audio_command(AudioCommand::Stop);
+    }
+
+    /// Whether the audio task is up to take commands
+    pub fn audio_healthy(&self) -> bool {
+        self.task_alive.load(Ordering::SeqCst)
+    }
+
+    /// Hand a command to the audio task, bringing the task back up if it died
+    fn send_audio_command(&self, cmd: AudioCommand) {
+        let mut tx = self.audio_cmd_tx.lock().expect("Poisoned");
+
+        let cmd = if self.audio_healthy() {
+            match tx.send(cmd) {
+                Ok(()) => return,
+                Err(err) => err.0,
             }
+        } else {
+            cmd
+        };
+
+        let Some(me) = self.me.get().and_then(Weak::upgrade) else {
+            log::error!(target: "bt", "Audio task is not running and can't be restarted");
+            return;
+        };
+
+        log::warn!(target: "bt", "Audio task is not running, restarting it");
+        let (new_tx, rx) = std::sync::mpsc::channel();
+        spawn_audio_task(me, rx);
+        *tx = new_tx;
+
+        if tx.send(cmd).is_err() {
+            log::error!(target: "bt", "Audio task died right after restarting");
         }
     }
 
-    pub fn play_audio(&self, data: &'static [u8]) {
-        AUDIO_GEN.fetch_add(1, Ordering::SeqCst);
-        self.audio_cmd_tx.send(AudioCommand::Play(data)).ok();
+    /// Let the speaker know whether something is playing. No AVRCP status or track title is
+    /// sent: those are pushed by the AVRCP target role and we only bring up the controller, so
+    /// there's no channel for them. This goes through the A2DP stream itself instead: with
+    /// `set_suspend_when_idle` the stream is suspended while stopped, which speakers that
+    /// power down or mute on their own read as "nothing playing".
+    pub fn set_playback_status(&self, status: PlaybackStatus) {
+        self.playing
+            .store(status == PlaybackStatus::Playing, Ordering::SeqCst);
+        if !self.is_connected() {
+            return;
+        }
+
+        match status {
+            PlaybackStatus::Playing if self.media_suspended.swap(false, Ordering::SeqCst) => {
+                log::debug!(target: "bt", "Resuming the A2DP stream");
+                unsafe { esp_a2d_media_ctrl(esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_START) };
+            }
+            PlaybackStatus::Stopped
+                if self.suspend_when_idle.load(Ordering::Relaxed)
+                    && !self.media_suspended.swap(true, Ordering::SeqCst) =>
+            {
+                log::debug!(target: "bt", "Suspending the A2DP stream");
+                unsafe { esp_a2d_media_ctrl(esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_SUSPEND) };
+            }
+            PlaybackStatus::Playing | PlaybackStatus::Stopped => {}
+        }
+    }
+
+    pub fn playback_status(&self) -> PlaybackStatus {
+        if self.playing.load(Ordering::SeqCst) {
+            PlaybackStatus::Playing
+        } else {
+            PlaybackStatus::Stopped
+        }
+    }
+
+    /// On by default, being the only playback signal the speaker gets, see
+    /// `set_playback_status`. A suspended stream takes a moment to start again though, turn
+    /// it off if that delay on the next capture sound matters more.
+    pub fn set_suspend_when_idle(&self, suspend: bool) {
+        self.suspend_when_idle.store(suspend, Ordering::Relaxed);
+        if !suspend && self.media_suspended.swap(false, Ordering::SeqCst) {
+            // Back to streaming silence between clips
+            unsafe { esp_a2d_media_ctrl(esp_a2d_media_ctrl_t_ESP_A2D_MEDIA_CTRL_START) };
+        }
+    }
+
+    /// Silence the speaker without stopping anything: the stream stays up and clips play
+    /// through as zeros, so unmuting is instant where a stopped stream can take seconds
+    /// to come back on some speakers
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+        log::info!(target: "bt", "Audio {}", if muted { "muted" } else { "unmuted" });
+    }
+
+    /// Pause or resume the audio task, pausing drops whatever was still queued
+    pub fn set_awake(&self, awake: bool) {
+        self.awake.store(awake, Ordering::Relaxed);
+        if !awake {
+            self.flush_ringbuffer();
+        }
+    }
+
+    pub fn is_awake(&self) -> bool {
+        self.awake.load(Ordering::Relaxed)
+    }
+
+    /// Media format negotiated with the connected sink, if any
+    pub fn audio_format(&self) -> Option<AudioFormat> {
+        self.audio_format.read().unwrap().clone()
+    }
+
+    pub fn audio_config(&self) -> AudioConfig {
+        *self.audio_config.read().unwrap()
     }
 
+    /// Retune the audio task, taken up from the next clip.
+    /// Fails with `BtError::InvalidAudioConfig` if it doesn't fit the ring buffer.
+    pub fn set_audio_config(&self, config: AudioConfig) -> Result<()> {
+        config.validate(self.ring_buf.size())?;
+        *self.audio_config.write().unwrap() = config;
+        log::info!(target: "bt", "Audio tuning set to {config:?}");
+        Ok(())
+    }
+
+    /// Whether an A2DP sink is connected and streaming
+    pub fn is_connected(&self) -> bool {
+        self.sink_connected.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `BtError::Busy` while a discovery or another connection is running
     pub fn a2dp_connect(&self, device: &BtDevice) -> Result<()> {
+        let _busy = BusyGuard::acquire(&self.busy)?;
         let mut conn = self.connection.write().unwrap();
 
         if conn.is_some() {
@@ -275,74 +947,127 @@ impl BluetoothAudio {
 
         let addr = device.addr.clone();
 
-        *conn = Some(device.clone());
-
         self.a2dp.connect_source(&addr)?;
+        *conn = Some(device.clone());
 
         Ok(())
     }
 
+    /// Devices we paired with, their link keys survive reboots
+    pub fn bonded_devices(&self) -> Result<Vec<BdAddr>> {
+        let mut count = unsafe { esp_bt_gap_get_bond_device_num() };
+        if count <= 0 {
+            return Ok(vec![]);
+        }
+        let mut list: Vec<esp_bd_addr_t> = vec![[0; 6]; count as usize];
+        esp!(unsafe { esp_bt_gap_get_bond_device_list(&mut count, list.as_mut_ptr()) })?;
+        list.truncate(count.max(0) as usize);
+
+        Ok(list.into_iter().map(BdAddr::from_bytes).collect())
+    }
+
+    /// Drop the bond with `addr`, it has to pair again next time. `false` if there was none.
+    pub fn forget_device(&self, addr: BdAddr) -> Result<bool> {
+        if !self.bonded_devices()?.contains(&addr) {
+            return Ok(false);
+        }
+        let mut bytes = addr.addr();
+        esp!(unsafe { esp_bt_gap_remove_bond_device(bytes.as_mut_ptr()) })?;
+        log::info!(target: "bt", "Forgot {addr}");
+
+        Ok(true)
+    }
+
     pub fn discovered_devices(&self) -> Arc<RwLock<Vec<BtDevice>>> {
         self.discovered_devices.clone()
     }
 
-    pub fn start_discovery(&self, on_discover: Option<fn(BtDevice) -> ()>) -> Result<()> {
+    /// Forget every device found so far
+    pub fn clear_discovered(&self) {
+        self.discovered_devices.write().expect("Poisoned").clear();
+    }
+
+    /// Forget devices that haven't answered within `ttl`, returns how many were dropped
+    pub fn prune_discovered(&self, ttl: Duration) -> usize {
+        let mut devices = self.discovered_devices.write().expect("Poisoned");
+        let before = devices.len();
+        devices.retain(|device| device.seen_within(ttl));
+        before - devices.len()
+    }
+
+    /// Start an inquiry. `fresh` forgets the devices found by earlier ones first.
+    pub fn start_discovery(
+        &self,
+        on_discover: Option<fn(BtDevice) -> ()>,
+        fresh: bool,
+    ) -> Result<()> {
+        // Check and set in one go, two callers racing here would both subscribe to GAP
         if self
             .is_in_discovery
-            .load(std::sync::atomic::Ordering::Relaxed)
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
         {
             return Ok(());
         }
 
-        self.is_in_discovery
-            .store(true, std::sync::atomic::Ordering::Relaxed);
+        if fresh {
+            self.clear_discovered();
+        }
+
+        let result = self.subscribe_and_discover(on_discover);
+        if result.is_err() {
+            // Leave nothing behind, so the next attempt starts from scratch
+            let _ = self.gap.unsubscribe();
+            self.is_in_discovery.store(false, Ordering::SeqCst);
+        }
+        result
+    }
 
+    fn subscribe_and_discover(&self, on_discover: Option<fn(BtDevice) -> ()>) -> Result<()> {
         let devices = self.discovered_devices.clone();
         self.gap.subscribe(move |event| match event {
             esp_idf_svc::bt::gap::GapEvent::DeviceDiscovered { bd_addr, props } => {
+                let mut device = BtDevice::from_addr(bd_addr);
+                device.last_seen = Some(Instant::now());
                 for prop in props {
-                    let p = prop.prop();
-                    let mut device_name = None;
-                    let addr = bd_addr;
-                    match p {
+                    match prop.prop() {
                         esp_idf_svc::bt::gap::DeviceProp::Eir(eir) => {
                             let name = eir.local_name::<BtClassic, BtClassicDriver>();
                             if let Some(name) = name {
-                                device_name = Some(name.to_string());
+                                device.name = Some(Arc::new(name.to_string()));
                             }
                         }
+                        esp_idf_svc::bt::gap::DeviceProp::Cod(cod) => {
+                            device.audio_sink = cod.major() == CodMajorDeviceType::AudioVideo;
+                        }
                         _ => {}
                     }
+                }
 
-                    let device = {
-                        if let Some(name) = device_name {
-                            BtDevice {
-                                addr,
-                                name: Some(Arc::new(name)),
-                            }
-                        } else {
-                            BtDevice { name: None, addr }
-                        }
-                    };
-                    let mut devices = devices.write().expect("Poisoned");
+                let mut devices = devices.write().expect("Poisoned");
 
-                    if !devices.contains(&device) {
-                        devices.push(device.clone());
-                        if let Some(callback) = on_discover {
-                            callback(device.clone());
-                        }
-                    } else {
-                        let (i, other_device) = devices
-                            .iter()
-                            .enumerate()
-                            .find(|(_, d)| **d == device)
-                            .unwrap();
-
-                        if other_device.name.is_none() {
-                            devices[i] = device;
-                        }
+                if let Some(other_device) = devices.iter_mut().find(|d| **d == device) {
+                    // Later inquiry responses may carry what the first one lacked
+                    if other_device.name.is_none() {
+                        other_device.name = device.name;
+                    }
+                    other_device.audio_sink |= device.audio_sink;
+                    other_device.last_seen = device.last_seen;
+                } else {
+                    devices.push(device.clone());
+                    if let Some(callback) = on_discover {
+                        callback(device);
                     }
-                    drop(devices);
+                }
+            }
+            // Answer to `request_name`
+            esp_idf_svc::bt::gap::GapEvent::RemoteNameResolved { bd_addr, name, .. } => {
+                if name.is_empty() {
+                    return;
+                }
+                let mut devices = devices.write().expect("Poisoned");
+                if let Some(device) = devices.iter_mut().find(|d| d.addr == bd_addr) {
+                    device.name = Some(Arc::new(name.to_string()));
                 }
             }
             _ => {}
@@ -354,16 +1079,184 @@ impl BluetoothAudio {
     }
 
     pub fn stop_discovery(&self) -> Result<()> {
-        if !self
-            .is_in_discovery
-            .load(std::sync::atomic::Ordering::Relaxed)
-        {
+        if !self.is_in_discovery.load(Ordering::SeqCst) {
             return Ok(());
         }
 
-        self.gap.stop_discovery()?;
-        self.gap.unsubscribe()?;
+        // Unsubscribe and clear the flag even if stopping failed, or discovery stays wedged
+        let stopped = self.gap.stop_discovery();
+        let unsubscribed = self.gap.unsubscribe();
+        self.is_in_discovery.store(false, Ordering::SeqCst);
 
+        stopped?;
+        unsubscribed?;
         Ok(())
     }
+
+    /// Scan for `duration` or until `max` devices showed up, whatever comes first.
+    /// `fresh` forgets earlier results first, otherwise they count towards `max`.
+    /// Fails with `BtError::Busy` while another scan or a connection is running.
+    pub fn discover_devices(
+        &self,
+        duration: Duration,
+        max: usize,
+        fresh: bool,
+    ) -> Result<Vec<BtDevice>> {
+        self.discover_filtered(duration, max, fresh, |_| true)
+    }
+
+    /// Like `discover_devices` but only keeps audio/video devices (speakers, headphones...)
+    pub fn discover_audio_sinks(
+        &self,
+        duration: Duration,
+        max: usize,
+        fresh: bool,
+    ) -> Result<Vec<BtDevice>> {
+        self.discover_filtered(duration, max, fresh, BtDevice::is_audio_sink)
+    }
+
+    fn discover_filtered(
+        &self,
+        duration: Duration,
+        max: usize,
+        fresh: bool,
+        filter: fn(&BtDevice) -> bool,
+    ) -> Result<Vec<BtDevice>> {
+        let _busy = BusyGuard::acquire(&self.busy)?;
+        self.start_discovery(None, fresh)?;
+
+        let deadline = std::time::Instant::now() + duration;
+        while std::time::Instant::now() < deadline {
+            if self.count_discovered(filter) >= max {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        // End the inquiry but keep listening to GAP, the names come back as events
+        let inquiry = self.gap.stop_discovery();
+        if inquiry.is_ok() {
+            let mut names = NameRequests::new(self, max, filter);
+            while let Some(wait) = names.next_wait(self) {
+                std::thread::sleep(wait);
+            }
+        }
+        self.stop_discovery()?;
+        inquiry?;
+
+        Ok(self.take_discovered(max, filter))
+    }
+
+    /// `discover_devices` without holding a thread up for the whole scan.
+    /// Dropping the future before it's done stops the scan.
+    pub async fn discover_devices_async(
+        &self,
+        duration: Duration,
+        max: usize,
+        fresh: bool,
+    ) -> Result<Vec<BtDevice>> {
+        self.discover_filtered_async(duration, max, fresh, |_| true).await
+    }
+
+    /// `discover_audio_sinks` counterpart of `discover_devices_async`
+    pub async fn discover_audio_sinks_async(
+        &self,
+        duration: Duration,
+        max: usize,
+        fresh: bool,
+    ) -> Result<Vec<BtDevice>> {
+        self.discover_filtered_async(duration, max, fresh, BtDevice::is_audio_sink).await
+    }
+
+    async fn discover_filtered_async(
+        &self,
+        duration: Duration,
+        max: usize,
+        fresh: bool,
+        filter: fn(&BtDevice) -> bool,
+    ) -> Result<Vec<BtDevice>> {
+        let _busy = BusyGuard::acquire(&self.busy)?;
+        let mut timer = EspTaskTimerService::new()?.timer_async()?;
+        self.start_discovery(None, fresh)?;
+        let discovery = DiscoveryGuard(self);
+
+        let deadline = std::time::Instant::now() + duration;
+        while std::time::Instant::now() < deadline {
+            if self.count_discovered(filter) >= max {
+                break;
+            }
+            timer.after(Duration::from_millis(100)).await?;
+        }
+
+        // End the inquiry but keep listening to GAP, the names come back as events
+        let inquiry = self.gap.stop_discovery();
+        if inquiry.is_ok() {
+            let mut names = NameRequests::new(self, max, filter);
+            while let Some(wait) = names.next_wait(self) {
+                timer.after(wait).await?;
+            }
+        }
+        discovery.finish()?;
+        inquiry?;
+
+        Ok(self.take_discovered(max, filter))
+    }
+
+    /// How many times a nameless device is asked for its name, 0 retries asks once
+    pub fn set_name_retries(&self, retries: usize) {
+        self.name_retries.store(retries, Ordering::Relaxed);
+    }
+
+    fn name_attempts(&self) -> usize {
+        1 + self.name_retries.load(Ordering::Relaxed)
+    }
+
+    fn nameless(&self, max: usize, filter: fn(&BtDevice) -> bool) -> Vec<BdAddr> {
+        self.take_discovered(max, filter)
+            .iter()
+            .filter(|d| d.name.is_none())
+            .map(BtDevice::addr)
+            .collect()
+    }
+
+    fn is_named(&self, addr: BdAddr) -> bool {
+        self.discovered_devices
+            .read()
+            .expect("Poisoned")
+            .iter()
+            .any(|d| d.addr == addr && d.name.is_some())
+    }
+
+    /// Send a remote name request, the answer comes in as a GAP event. `false` if the stack
+    /// didn't take it.
+    fn request_name(&self, addr: BdAddr) -> bool {
+        let mut bytes = addr.addr();
+        match esp!(unsafe { esp_bt_gap_read_remote_name(bytes.as_mut_ptr()) }) {
+            Ok(()) => true,
+            Err(err) => {
+                log::debug!(target: "bt", "Failed to ask {addr} for its name: {err}");
+                false
+            }
+        }
+    }
+
+    fn count_discovered(&self, filter: fn(&BtDevice) -> bool) -> usize {
+        self.discovered_devices
+            .read()
+            .expect("Poisoned")
+            .iter()
+            .filter(|d| filter(d))
+            .count()
+    }
+
+    fn take_discovered(&self, max: usize, filter: fn(&BtDevice) -> bool) -> Vec<BtDevice> {
+        self.discovered_devices
+            .read()
+            .expect("Poisoned")
+            .iter()
+            .filter(|d| filter(d))
+            .take(max)
+            .cloned()
+            .collect()
+    }
 }
@@ -1,20 +1,37 @@
 use std::fmt::Debug;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Ok;
-use esp_idf_svc::hal::{
-    gpio::{Input, InputPin, OutputPin, PinDriver, Pull, InterruptType},
-    peripheral::Peripheral,
+use esp_idf_svc::{
+    hal::{
+        gpio::{Input, InputPin, OutputPin, PinDriver, Pull, InterruptType},
+        peripheral::Peripheral,
+    },
+    sys::{gpio_get_level, gpio_intr_enable},
 };
 
 pub type ButtonCallback = Arc<dyn Fn() + Send + Sync>;
 
+/// Pin level while the button is held down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ActiveLevel {
+    /// Button shorts the pin to ground, internal pull-up
+    #[default]
+    Low,
+    /// Button ties the pin to 3V3, internal pull-down (an external one helps on long wires)
+    High,
+}
+
 pub struct InputButton<P: InputPin> {
     driver: Arc<Mutex<PinDriver<'static, P, Input>>>,
     pressed: Arc<AtomicBool>,
+    /// Held down as of the last edge, kept by the ISR so reading it never locks the driver
+    active: Arc<AtomicBool>,
     last_press_ms: Arc<AtomicUsize>,
     debounce_ms: usize,
+    active_level: ActiveLevel,
 }
 
 impl<P: InputPin> Debug for InputButton<P> {
@@ -24,16 +41,31 @@ impl<P: InputPin> Debug for InputButton<P> {
 }
 
 impl<P: InputPin + OutputPin> InputButton<P> {
-    pub fn new(pin: impl Peripheral<P = P> + 'static, debounce_ms: usize) -> anyhow::Result<Self> {
+    pub fn new(
+        pin: impl Peripheral<P = P> + 'static,
+        debounce_ms: usize,
+        active_level: ActiveLevel,
+    ) -> anyhow::Result<Self> {
         let mut driver = PinDriver::input(pin)?;
-        driver.set_pull(Pull::Up)?;
-        driver.set_interrupt_type(InterruptType::NegEdge)?;
+        // Idle at the opposite level. Both edges interrupt to keep `active` current, only the
+        // one towards the active level counts as a press.
+        driver.set_pull(match active_level {
+            ActiveLevel::Low => Pull::Up,
+            ActiveLevel::High => Pull::Down,
+        })?;
+        driver.set_interrupt_type(InterruptType::AnyEdge)?;
+        let active = match active_level {
+            ActiveLevel::Low => driver.is_low(),
+            ActiveLevel::High => driver.is_high(),
+        };
 
         let mut btn = Self {
             driver: Arc::new(Mutex::new(driver)),
             pressed: Arc::new(AtomicBool::new(false)),
+            active: Arc::new(AtomicBool::new(active)),
             last_press_ms: Arc::new(AtomicUsize::new(0)),
             debounce_ms,
+            active_level,
         };
 
         btn.setup_interrupt().unwrap();
@@ -45,12 +77,22 @@ impl<P: InputPin + OutputPin> InputButton<P> {
     /// Callback should be fast and non-blocking.
     pub fn setup_interrupt(&mut self) -> anyhow::Result<()> {
         let pressed = self.pressed.clone();
+        let active = self.active.clone();
         let last_press = self.last_press_ms.clone();
         let debounce = self.debounce_ms;
-        let driver = self.driver.clone();
+        let active_high = self.active_level == ActiveLevel::High;
         let mut locked_driver = self.driver.lock().unwrap();
+        let pin = locked_driver.pin();
         unsafe {
             locked_driver.subscribe(move || {
+                // Raw calls rather than going through the driver, taking its lock here could stall
+                let is_active = (gpio_get_level(pin) != 0) == active_high;
+                active.store(is_active, Ordering::SeqCst);
+                if !is_active {
+                    gpio_intr_enable(pin);
+                    return;
+                }
+
                 let now_ms = (esp_idf_svc::sys::esp_timer_get_time() / 1000) as usize;
                 let last = last_press.load(Ordering::SeqCst);
 
@@ -63,8 +105,7 @@ impl<P: InputPin + OutputPin> InputButton<P> {
                     // Still in debounce window, update timestamp to extend the window
                     last_press.store(now_ms, Ordering::SeqCst);
                 }
-                let mut driver = driver.lock().unwrap();
-                driver.enable_interrupt().unwrap();
+                gpio_intr_enable(pin);
             })?;
         }
         locked_driver.enable_interrupt()?;
@@ -76,9 +117,44 @@ impl<P: InputPin + OutputPin> InputButton<P> {
         self.pressed.swap(false, Ordering::Relaxed)
     }
 
-    /// Get current button state (true = pressed / low).
+    /// Get current button state (true = held down, at its `ActiveLevel`).
+    /// Lock-free, it's the level as of the last edge the ISR saw.
     pub fn is_active(&self) -> bool {
-        let driver = self.driver.lock().unwrap();
-        driver.is_low()
+        self.active.load(Ordering::SeqCst)
+    }
+}
+
+/// Fires once when a condition (e.g. two buttons down together) has held for `hold`,
+/// then again only after it was released
+#[derive(Debug)]
+pub struct HoldCombo {
+    hold: Duration,
+    held_since: Option<Instant>,
+    fired: bool,
+}
+
+impl HoldCombo {
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            held_since: None,
+            fired: false,
+        }
+    }
+
+    /// Feed the current state, `true` the moment the hold time is reached
+    pub fn update(&mut self, held: bool, now: Instant) -> bool {
+        if !held {
+            self.held_since = None;
+            self.fired = false;
+            return false;
+        }
+
+        let since = *self.held_since.get_or_insert(now);
+        if self.fired || now.duration_since(since) < self.hold {
+            return false;
+        }
+        self.fired = true;
+        true
     }
 }
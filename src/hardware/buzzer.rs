@@ -0,0 +1,208 @@
+//! Piezo buzzer on a LEDC PWM channel, the cheapest way to make a board audible.
+//!
+//! Plays melodies of `(frequency, duration)` notes on its own task, a new melody cuts the one
+//! playing short. Runs next to the main audio output rather than replacing it.
+
+use std::{
+    fmt::Debug,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use esp_idf_svc::{
+    hal::{
+        gpio::OutputPin,
+        ledc::{CHANNEL0, TIMER0},
+        peripheral::Peripheral,
+    },
+    sys::{
+        esp, ledc_channel_config, ledc_channel_config_t, ledc_channel_t_LEDC_CHANNEL_0,
+        ledc_mode_t_LEDC_LOW_SPEED_MODE, ledc_set_duty, ledc_set_freq,
+        ledc_timer_bit_t_LEDC_TIMER_10_BIT, ledc_timer_config, ledc_timer_config_t,
+        ledc_timer_t_LEDC_TIMER_0, ledc_update_duty, EspError,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app::Team;
+
+/// `(frequency in Hz, length in ms)`, a frequency of 0 is a rest
+pub type Note = (u16, u16);
+
+/// Half of the 10 bit duty range, the loudest a piezo gets
+const DUTY_ON: u32 = 512;
+
+/// Frequency the timer is set up with, every note retunes it
+const IDLE_FREQ_HZ: u32 = 1_000;
+
+/// Lowest and highest note. The 10 bit timer runs off the 80MHz APB clock through a divider
+/// of at most 1024, which bottoms out at ~76.3Hz, lower notes fail to set
+const MIN_FREQ_HZ: u16 = 77;
+const MAX_FREQ_HZ: u16 = 20_000;
+
+/// Longest melody accepted, and the longest note in it
+pub const MAX_NOTES: usize = 32;
+pub const MAX_NOTE_MS: u16 = 2_000;
+
+/// Each team's capture melody
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Melodies {
+    pub red: Vec<Note>,
+    pub blue: Vec<Note>,
+}
+
+impl Default for Melodies {
+    /// Red rises, blue falls, told apart without looking
+    fn default() -> Self {
+        Self {
+            red: vec![(523, 120), (659, 120), (784, 240)],
+            blue: vec![(784, 120), (659, 120), (523, 240)],
+        }
+    }
+}
+
+impl Melodies {
+    pub fn get(&self, team: Team) -> &[Note] {
+        match team {
+            Team::Red => &self.red,
+            Team::Blue => &self.blue,
+        }
+    }
+
+    /// Keep melodies short and within what a piezo can play
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for melody in [&self.red, &self.blue] {
+            if melody.len() > MAX_NOTES {
+                return Err(anyhow::anyhow!("A melody can have at most {MAX_NOTES} notes"));
+            }
+            for &(freq, ms) in melody {
+                if freq != 0 && !(MIN_FREQ_HZ..=MAX_FREQ_HZ).contains(&freq) {
+                    return Err(anyhow::anyhow!(
+                        "{freq}Hz is outside {MIN_FREQ_HZ}Hz to {MAX_FREQ_HZ}Hz"
+                    ));
+                }
+                if ms > MAX_NOTE_MS {
+                    return Err(anyhow::anyhow!("Notes can be at most {MAX_NOTE_MS}ms"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Buzzer {
+    melody_tx: Mutex<Sender<Vec<Note>>>,
+    // Held so nothing else drives the same LEDC timer and channel
+    _timer: TIMER0,
+    _channel: CHANNEL0,
+}
+
+impl Debug for Buzzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Buzzer")
+    }
+}
+
+impl Buzzer {
+    pub fn init(
+        timer: TIMER0,
+        channel: CHANNEL0,
+        pin: impl Peripheral<P = impl OutputPin> + 'static,
+    ) -> anyhow::Result<Self> {
+        let pin = pin.into_ref().pin();
+        esp!(unsafe {
+            ledc_timer_config(&ledc_timer_config_t {
+                speed_mode: ledc_mode_t_LEDC_LOW_SPEED_MODE,
+                duty_resolution: ledc_timer_bit_t_LEDC_TIMER_10_BIT,
+                timer_num: ledc_timer_t_LEDC_TIMER_0,
+                freq_hz: IDLE_FREQ_HZ,
+                ..Default::default()
+            })
+        })?;
+        esp!(unsafe {
+            ledc_channel_config(&ledc_channel_config_t {
+                gpio_num: pin,
+                speed_mode: ledc_mode_t_LEDC_LOW_SPEED_MODE,
+                channel: ledc_channel_t_LEDC_CHANNEL_0,
+                timer_sel: ledc_timer_t_LEDC_TIMER_0,
+                duty: 0,
+                ..Default::default()
+            })
+        })?;
+        log::info!("Init buzzer on GPIO{pin}");
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || play_melodies(rx));
+
+        Ok(Self {
+            melody_tx: Mutex::new(tx),
+            _timer: timer,
+            _channel: channel,
+        })
+    }
+
+    /// Start playing `melody`, cutting off whatever was playing
+    pub fn play_melody(&self, melody: &[Note]) {
+        if self
+            .melody_tx
+            .lock()
+            .expect("Poisoned")
+            .send(melody.to_vec())
+            .is_err()
+        {
+            log::warn!("Buzzer task is not running, melody dropped");
+        }
+    }
+
+    /// Cut off whatever is playing
+    pub fn stop(&self) {
+        self.play_melody(&[]);
+    }
+}
+
+fn play_melodies(rx: Receiver<Vec<Note>>) {
+    let mut next = None;
+    loop {
+        let melody = match next.take() {
+            Some(melody) => melody,
+            None => match rx.recv() {
+                Ok(melody) => melody,
+                Err(_) => break,
+            },
+        };
+
+        for (freq, ms) in melody {
+            tone(freq);
+            // Waiting on the channel rather than sleeping lets a newer melody cut in
+            match rx.recv_timeout(Duration::from_millis(ms.into())) {
+                Ok(newer) => {
+                    next = Some(newer);
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        tone(0);
+    }
+    log::warn!("Buzzer task exited");
+}
+
+/// Sound `freq`, or go quiet for 0
+fn tone(freq: u16) {
+    let duty = if freq == 0 { 0 } else { DUTY_ON };
+    let result = (|| -> Result<(), EspError> {
+        let mode = ledc_mode_t_LEDC_LOW_SPEED_MODE;
+        if freq != 0 {
+            esp!(unsafe { ledc_set_freq(mode, ledc_timer_t_LEDC_TIMER_0, freq.into()) })?;
+        }
+        esp!(unsafe { ledc_set_duty(mode, ledc_channel_t_LEDC_CHANNEL_0, duty) })?;
+        esp!(unsafe { ledc_update_duty(mode, ledc_channel_t_LEDC_CHANNEL_0) })
+    })();
+    if let Err(err) = result {
+        log::warn!("Buzzer failed to play {freq}Hz: {err}");
+    }
+}
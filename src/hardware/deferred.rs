@@ -0,0 +1,142 @@
+//! When values saved with `Storage::set_deferred` reach flash. Kept apart from the NVS handle,
+//! nothing in here needs the IDF, so it builds and is tested on the host.
+
+use std::time::{Duration, Instant};
+
+/// Least time between two flushes of `set_deferred` values, bounds the flash wear of
+/// frequently saved state to one write per key per interval
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Values saved with `set_deferred` that haven't reached flash yet, newest per key
+#[derive(Debug, Default)]
+pub struct Pending {
+    values: Vec<(&'static str, Vec<u8>)>,
+    last_flush: Option<Instant>,
+}
+
+impl Pending {
+    pub fn put(&mut self, key: &'static str, bytes: Vec<u8>) {
+        match self.values.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, value)) => *value = bytes,
+            None => self.values.push((key, bytes)),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.values
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub fn drop_key(&mut self, key: &str) {
+        self.values.retain(|(k, _)| *k != key);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Whether there's something to write and the last flush is `FLUSH_INTERVAL` ago
+    pub fn due(&self, now: Instant) -> bool {
+        !self.values.is_empty()
+            && self
+                .last_flush
+                .map_or(true, |last| now.duration_since(last) >= FLUSH_INTERVAL)
+    }
+
+    /// Everything to write for a flush at `now`, due or not, the interval starts over
+    pub fn take(&mut self, now: Instant) -> Vec<(&'static str, Vec<u8>)> {
+        self.last_flush = Some(now);
+        std::mem::take(&mut self.values)
+    }
+
+    /// Hand back a value that failed to write for the next flush, unless a newer one came in
+    /// meanwhile
+    pub fn retry(&mut self, key: &'static str, bytes: Vec<u8>) {
+        if self.get(key).is_none() {
+            self.put(key, bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a `flush_if_due` every second makes while a checkpoint is saved every second
+    fn writes_over(pending: &mut Pending, start: Instant, secs: u64) -> usize {
+        let mut writes = 0;
+        for sec in 0..secs {
+            let now = start + Duration::from_secs(sec);
+            pending.put("checkpoint", sec.to_le_bytes().to_vec());
+            if pending.due(now) {
+                writes += pending.take(now).len();
+            }
+        }
+        writes
+    }
+
+    #[test]
+    fn nothing_saved_is_never_due() {
+        let pending = Pending::default();
+        assert!(!pending.due(Instant::now()));
+    }
+
+    #[test]
+    fn first_value_is_due_straight_away() {
+        let mut pending = Pending::default();
+        pending.put("checkpoint", vec![1]);
+        assert!(pending.due(Instant::now()));
+    }
+
+    #[test]
+    fn repeated_saves_within_the_interval_flush_once() {
+        let start = Instant::now();
+        let mut pending = Pending::default();
+        pending.take(start);
+
+        let secs = FLUSH_INTERVAL.as_secs();
+        assert_eq!(writes_over(&mut pending, start, secs), 0);
+        assert!(pending.due(start + FLUSH_INTERVAL));
+        let flushed = pending.take(start + FLUSH_INTERVAL);
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].1, (secs - 1).to_le_bytes());
+    }
+
+    #[test]
+    fn steady_saves_flush_once_per_interval() {
+        let start = Instant::now();
+        let mut pending = Pending::default();
+        pending.take(start);
+
+        let writes = writes_over(&mut pending, start, 3 * FLUSH_INTERVAL.as_secs());
+        assert_eq!(writes, 2);
+    }
+
+    #[test]
+    fn stop_flushes_what_is_not_due_yet() {
+        let start = Instant::now();
+        let mut pending = Pending::default();
+        pending.take(start);
+        pending.put("checkpoint", vec![1]);
+        pending.put("history", vec![2]);
+
+        let stop = start + Duration::from_secs(1);
+        assert!(!pending.due(stop));
+        assert_eq!(pending.take(stop).len(), 2);
+        assert!(pending.is_empty());
+        assert!(!pending.due(stop + FLUSH_INTERVAL));
+    }
+
+    #[test]
+    fn failed_write_is_retried_unless_superseded() {
+        let mut pending = Pending::default();
+        pending.retry("checkpoint", vec![1]);
+        assert_eq!(pending.get("checkpoint"), Some(&[1][..]));
+
+        pending.put("checkpoint", vec![2]);
+        pending.retry("checkpoint", vec![1]);
+        assert_eq!(pending.get("checkpoint"), Some(&[2][..]));
+    }
+}
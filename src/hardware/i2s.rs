@@ -0,0 +1,173 @@
+//! Wired audio output: streams the PCM clips to an I2S amplifier instead of a BT speaker.
+//!
+//! Same design as the Bluetooth sink: callers hand clips to a dedicated audio task over a
+//! channel and a generation counter cuts a clip short as soon as a newer one is requested.
+//! The I2S DMA buffers play the role of the ring buffer, so the task writes straight into
+//! the driver.
+
+use std::fmt::Debug;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    mpsc::{Receiver, Sender},
+    Arc, Mutex,
+};
+
+use esp_idf_svc::hal::{
+    delay::TickType,
+    gpio::{AnyIOPin, InputPin, OutputPin},
+    i2s::{
+        config::{
+            Config, DataBitWidth, SlotMode, StdClkConfig, StdConfig, StdGpioConfig,
+            StdSlotConfig,
+        },
+        I2s, I2sDriver, I2sTx,
+    },
+    peripheral::Peripheral,
+};
+
+use crate::{app::AudioSink, hardware::bt::PCM_BYTES_PER_SEC};
+
+enum AudioCommand {
+    Play(&'static [u8]),
+    Stop,
+}
+
+/// State shared between the sink and its audio task
+struct Shared {
+    generation: AtomicU32,
+    awake: AtomicBool,
+    task_alive: AtomicBool,
+}
+
+pub struct I2sAudio {
+    shared: Arc<Shared>,
+    cmd_tx: Mutex<Sender<AudioCommand>>,
+}
+
+impl Debug for I2sAudio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("I2S Audio")
+    }
+}
+
+impl I2sAudio {
+    pub fn init<I: I2s>(
+        i2s: impl Peripheral<P = I> + 'static,
+        bclk: impl Peripheral<P = impl InputPin + OutputPin> + 'static,
+        dout: impl Peripheral<P = impl OutputPin> + 'static,
+        ws: impl Peripheral<P = impl InputPin + OutputPin> + 'static,
+    ) -> anyhow::Result<Self> {
+        // Philips format like `StdConfig::philips`, with DMA buffers that go back to silence
+        // once played: otherwise the last one repeats until the next clip
+        let config = StdConfig::new(
+            Config::default().auto_clear(true),
+            StdClkConfig::from_sample_rate_hz((PCM_BYTES_PER_SEC / 4) as u32),
+            StdSlotConfig::philips_slot_default(DataBitWidth::Bits16, SlotMode::Stereo),
+            StdGpioConfig::default(),
+        );
+        let mut driver =
+            I2sDriver::<I2sTx>::new_std_tx(i2s, &config, bclk, dout, Option::<AnyIOPin>::None, ws)?;
+        driver.tx_enable()?;
+        log::info!("Init I2S Audio");
+
+        let shared = Arc::new(Shared {
+            generation: AtomicU32::new(0),
+            awake: true.into(),
+            task_alive: false.into(),
+        });
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn_audio_task(driver, shared.clone(), rx);
+
+        Ok(Self {
+            shared,
+            cmd_tx: Mutex::new(tx),
+        })
+    }
+
+    fn send_audio_command(&self, cmd: AudioCommand) {
+        // Unlike Bluetooth the driver dies with the task, so there's no restarting it
+        if self.cmd_tx.lock().expect("Poisoned").send(cmd).is_err() {
+            log::warn!("I2S audio task is not running, clip dropped");
+        }
+    }
+}
+
+impl AudioSink for I2sAudio {
+    fn play(&self, clip: &'static [u8]) {
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        self.send_audio_command(AudioCommand::Play(clip));
+    }
+
+    fn stop(&self) {
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        self.send_audio_command(AudioCommand::Stop);
+    }
+
+    /// A wired amplifier is always there
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.shared.task_alive.load(Ordering::SeqCst)
+    }
+
+    fn set_awake(&self, awake: bool) {
+        self.shared.awake.store(awake, Ordering::Relaxed);
+    }
+}
+
+/// Flags the audio task as dead when it exits, even by panicking
+struct AudioTaskGuard(Arc<Shared>);
+
+impl Drop for AudioTaskGuard {
+    fn drop(&mut self) {
+        self.0.task_alive.store(false, Ordering::SeqCst);
+        log::warn!("I2S audio task exited");
+    }
+}
+
+fn spawn_audio_task(
+    mut driver: I2sDriver<'static, I2sTx>,
+    shared: Arc<Shared>,
+    rx: Receiver<AudioCommand>,
+) {
+    shared.task_alive.store(true, Ordering::SeqCst);
+    std::thread::spawn(move || {
+        let _guard = AudioTaskGuard(shared.clone());
+        const CHUNK: usize = 512;
+        let timeout = TickType::new_millis(100).ticks();
+
+        loop {
+            match rx.recv() {
+                Ok(AudioCommand::Play(data)) => {
+                    // Paused while the device is in low-power mode
+                    while !shared.awake.load(Ordering::Relaxed) {
+                        std::thread::sleep(std::time::Duration::from_millis(100));
+                    }
+
+                    let my_gen = shared.generation.load(Ordering::SeqCst);
+
+                    for chunk in data.chunks(CHUNK) {
+                        // If a newer Play() happened or we went to sleep → exit immediately
+                        if shared.generation.load(Ordering::Relaxed) != my_gen
+                            || !shared.awake.load(Ordering::Relaxed)
+                        {
+                            break;
+                        }
+
+                        if let Err(err) = driver.write_all(chunk, timeout) {
+                            log::error!("I2S write failed: {err}");
+                            break;
+                        }
+                    }
+                }
+
+                // Nothing to flush, `auto_clear` turns played buffers into silence
+                Ok(AudioCommand::Stop) => {}
+
+                Err(_) => break,
+            }
+        }
+    });
+}
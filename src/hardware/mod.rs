@@ -1,3 +1,11 @@
+pub mod audio_gate;
 pub mod bt;
 pub mod buttons;
+pub mod buzzer;
+pub mod deferred;
+pub mod i2s;
+pub mod outputs;
+pub mod pins;
+pub mod ringbuf;
+pub mod storage;
 pub mod wifi;
\ No newline at end of file
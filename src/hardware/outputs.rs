@@ -0,0 +1,96 @@
+//! Digital outputs for props wired to the board (smoke machine, siren...), through a relay or
+//! a transistor rather than straight off the pin.
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use esp_idf_svc::hal::gpio::{AnyOutputPin, Output, PinDriver};
+
+struct State {
+    driver: PinDriver<'static, AnyOutputPin, Output>,
+    /// Level asked for by `set_active`
+    active: bool,
+    /// Bumped by every `pulse`, a pulse only ends itself if no newer one started
+    pulse: u32,
+    pulsing: bool,
+}
+
+impl State {
+    fn drive(&mut self, high: bool) {
+        let result = if high {
+            self.driver.set_high()
+        } else {
+            self.driver.set_low()
+        };
+        if let Err(err) = result {
+            let level = if high { "high" } else { "low" };
+            log::warn!("Failed to drive the game signal {level}: {err}");
+        }
+    }
+}
+
+/// An output that is high while something is going on, and can be pulsed on top of that
+pub struct GpioSignal {
+    state: Arc<Mutex<State>>,
+}
+
+impl Debug for GpioSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("GpioSignal")
+    }
+}
+
+impl GpioSignal {
+    /// Starts low
+    pub fn new(pin: AnyOutputPin) -> anyhow::Result<Self> {
+        let mut driver = PinDriver::output(pin)?;
+        driver.set_low()?;
+        log::info!("Init game signal on GPIO{}", driver.pin());
+        Ok(Self {
+            state: Arc::new(Mutex::new(State {
+                driver,
+                active: false,
+                pulse: 0,
+                pulsing: false,
+            })),
+        })
+    }
+
+    /// Hold the output high or low. A running pulse finishes first, only touches the pin when
+    /// the level changes.
+    pub fn set_active(&self, active: bool) {
+        let mut state = self.state.lock().expect("Poisoned");
+        if state.active == active {
+            return;
+        }
+        state.active = active;
+        if !state.pulsing {
+            state.drive(active);
+        }
+    }
+
+    /// Go high for `ms` without blocking, then back to what `set_active` last asked for
+    pub fn pulse(&self, ms: u32) {
+        let pulse = {
+            let mut state = self.state.lock().expect("Poisoned");
+            state.pulse = state.pulse.wrapping_add(1);
+            state.pulsing = true;
+            state.drive(true);
+            state.pulse
+        };
+
+        let state = self.state.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(ms.into()));
+            let mut state = state.lock().expect("Poisoned");
+            if state.pulse == pulse {
+                state.pulsing = false;
+                let active = state.active;
+                state.drive(active);
+            }
+        });
+    }
+}
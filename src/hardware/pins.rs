@@ -0,0 +1,46 @@
+//! Board wiring, every GPIO the firmware uses is picked here.
+//!
+//! `PinDriver` needs the concrete pin type, so the mapping is resolved at compile time.
+//! Re-wiring a board is a one-line change in the `board_pins!` invocation below:
+//! `<name>: <GpioN type> = <gpioN field of Pins>`.
+
+use esp_idf_svc::hal::gpio;
+
+use super::buttons::ActiveLevel;
+
+pub const BUTTON_DEBOUNCE_MS: usize = 50;
+
+/// How the team buttons are wired, to ground by default
+pub const BUTTON_ACTIVE_LEVEL: ActiveLevel = ActiveLevel::Low;
+
+macro_rules! board_pins {
+    ($($(#[$meta:meta])* $name:ident: $ty:ident = $field:ident),* $(,)?) => {
+        pub struct BoardPins {
+            $($(#[$meta])* pub $name: gpio::$ty,)*
+        }
+
+        impl BoardPins {
+            pub fn take(pins: gpio::Pins) -> Self {
+                Self {
+                    $($(#[$meta])* $name: pins.$field,)*
+                }
+            }
+        }
+    };
+}
+
+board_pins! {
+    red_button: Gpio19 = gpio19,
+    blue_button: Gpio18 = gpio18,
+    // Ends the game and silences everything, wired like the team buttons
+    stop_button: Gpio21 = gpio21,
+    // Wired amplifier, only driven when the audio output is I2S
+    i2s_bclk: Gpio26 = gpio26,
+    i2s_ws: Gpio25 = gpio25,
+    i2s_dout: Gpio22 = gpio22,
+    // Piezo for the team melodies, between the pin and ground
+    buzzer: Gpio23 = gpio23,
+    // High while a game runs, for props. Boards without them leave the pin free.
+    #[cfg(feature = "game-signal")]
+    game_signal: Gpio27 = gpio27,
+}
@@ -0,0 +1,90 @@
+//! Byte ring buffer from ESP-IDF, the FFI calls and their pointer juggling stay in here.
+//!
+//! The IDF ring buffer is thread-safe, one task can `send` while another (or the Bluetooth
+//! stack's callback) does `receive_into`.
+
+use esp_idf_svc::sys::{
+    vRingbufferDelete, vRingbufferReturnItem, xRingbufferCreate, xRingbufferGetCurFreeSize,
+    xRingbufferReceiveUpTo, xRingbufferSend, RingbufHandle_t,
+    RingbufferType_t_RINGBUF_TYPE_BYTEBUF, TickType_t,
+};
+
+pub struct RingBuffer {
+    handle: RingbufHandle_t,
+    size: usize,
+}
+
+// ESP-IDF ring buffers are thread-safe by design
+unsafe impl Send for RingBuffer {}
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    /// Allocate a `size` byte buffer, fails if the heap can't spare it
+    pub fn new(size: usize) -> anyhow::Result<Self> {
+        let handle = unsafe { xRingbufferCreate(size, RingbufferType_t_RINGBUF_TYPE_BYTEBUF) };
+        if handle.is_null() {
+            return Err(anyhow::anyhow!("Failed to allocate a {size} byte ring buffer"));
+        }
+        Ok(Self { handle, size })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Bytes that can be sent right now without waiting
+    pub fn free(&self) -> usize {
+        unsafe { xRingbufferGetCurFreeSize(self.handle) }
+    }
+
+    /// Bytes waiting to be received
+    pub fn len(&self) -> usize {
+        self.size.saturating_sub(self.free())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queue all of `data`, waiting up to `timeout` ticks for room. `false` if nothing was
+    /// queued, the buffer never takes part of it.
+    pub fn send(&self, data: &[u8], timeout: TickType_t) -> bool {
+        unsafe { xRingbufferSend(self.handle, data.as_ptr() as *const _, data.len(), timeout) != 0 }
+    }
+
+    /// Move up to `buf.len()` bytes into `buf` without waiting, returns how many. Can be less
+    /// than what's buffered when the data wraps around the end of the buffer.
+    pub fn receive_into(&self, buf: &mut [u8]) -> usize {
+        let mut size = 0;
+        unsafe {
+            let item = xRingbufferReceiveUpTo(self.handle, &mut size, 0, buf.len());
+            if item.is_null() {
+                return 0;
+            }
+            // The IDF never hands out more than asked for
+            core::ptr::copy_nonoverlapping(item as *const u8, buf.as_mut_ptr(), size);
+            vRingbufferReturnItem(self.handle, item);
+        }
+        size
+    }
+
+    /// Drop everything buffered
+    pub fn clear(&self) {
+        let mut size = 0;
+        unsafe {
+            loop {
+                let item = xRingbufferReceiveUpTo(self.handle, &mut size, 0, usize::MAX);
+                if item.is_null() {
+                    break;
+                }
+                vRingbufferReturnItem(self.handle, item);
+            }
+        }
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe { vRingbufferDelete(self.handle) };
+    }
+}
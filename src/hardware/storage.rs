@@ -0,0 +1,120 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::deferred::Pending;
+pub use super::deferred::FLUSH_INTERVAL;
+
+/// One NVS namespace holding values as JSON blobs
+pub struct Storage {
+    nvs: Mutex<EspNvs<NvsDefault>>,
+    namespace: &'static str,
+    pending: Mutex<Pending>,
+    /// Writes and erases that reached flash since boot, to keep an eye on wear
+    writes: AtomicU32,
+}
+
+impl Debug for Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Storage({})", self.namespace)
+    }
+}
+
+impl Storage {
+    /// `namespace` and keys are limited to 15 characters by NVS
+    pub fn new(partition: EspDefaultNvsPartition, namespace: &'static str) -> anyhow::Result<Self> {
+        Ok(Self {
+            nvs: Mutex::new(EspNvs::new(partition, namespace, true)?),
+            namespace,
+            pending: Mutex::new(Pending::default()),
+            writes: AtomicU32::new(0),
+        })
+    }
+
+    /// Sees values from `set_deferred` that haven't been flushed yet
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        if let Some(bytes) = self.pending.lock().expect("Poisoned").get(key) {
+            return Ok(Some(serde_json::from_slice(bytes)?));
+        }
+
+        let nvs = self.nvs.lock().expect("Poisoned");
+        let Some(len) = nvs.blob_len(key)? else {
+            return Ok(None);
+        };
+
+        let mut buf = vec![0; len];
+        match nvs.get_blob(key, &mut buf)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write straight to flash, replacing a deferred value for `key`
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(value)?;
+        self.pending.lock().expect("Poisoned").drop_key(key);
+        self.write(key, &bytes)
+    }
+
+    /// Keep `value` to write with the next `flush`, only the latest one per key reaches flash.
+    /// For state saved often, a power cut loses up to `FLUSH_INTERVAL` of it.
+    pub fn set_deferred<T: Serialize>(&self, key: &'static str, value: &T) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(value)?;
+        self.pending.lock().expect("Poisoned").put(key, bytes);
+        Ok(())
+    }
+
+    /// Erase `key`, deferred value included
+    pub fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.pending.lock().expect("Poisoned").drop_key(key);
+        if self.nvs.lock().expect("Poisoned").remove(key)? {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// `flush` if the last one was at least `FLUSH_INTERVAL` ago, call it regularly
+    pub fn flush_if_due(&self) -> anyhow::Result<()> {
+        if !self.pending.lock().expect("Poisoned").due(Instant::now()) {
+            return Ok(());
+        }
+        self.flush()
+    }
+
+    /// Write every deferred value now, on a clean stop or before a reboot
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let values = self.pending.lock().expect("Poisoned").take(Instant::now());
+        let mut result = Ok(());
+        for (key, bytes) in values {
+            if let Err(err) = self.write(key, &bytes) {
+                self.pending.lock().expect("Poisoned").retry(key, bytes);
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// Writes and erases that reached flash since boot
+    pub fn writes(&self) -> u32 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    /// Whether deferred values are waiting for a flush
+    pub fn dirty(&self) -> bool {
+        !self.pending.lock().expect("Poisoned").is_empty()
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        self.nvs.lock().expect("Poisoned").set_blob(key, bytes)?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -1,10 +1,184 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    net::Ipv4Addr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
 
 use anyhow::Ok;
-use esp_idf_svc::wifi::{AccessPointConfiguration, AsyncWifi, ClientConfiguration, EspWifi};
+use serde::{Deserialize, Serialize};
+use esp_idf_svc::{
+    sntp::{EspSntp, SntpConf, SyncStatus},
+    sys::{
+        esp, esp_wifi_ap_get_sta_list, esp_wifi_ap_get_sta_list_with_ip,
+        esp_wifi_set_max_tx_power, esp_wifi_set_ps, wifi_ps_type_t_WIFI_PS_MAX_MODEM,
+        wifi_ps_type_t_WIFI_PS_MIN_MODEM, wifi_sta_list_t, wifi_sta_mac_ip_list_t,
+    },
+    wifi::{
+        AccessPointConfiguration, AsyncWifi, AuthMethod, ClientConfiguration, Configuration,
+        EspWifi,
+    },
+};
+
+// TX power is in units of 0.25 dBm
+const FULL_TX_POWER: i8 = 78; // 19.5 dBm
+const LOW_TX_POWER: i8 = 34; // 8.5 dBm
+
+/// Where the clock is set from when we join a network with internet access
+pub const DEFAULT_NTP_SERVER: &str = "pool.ntp.org";
+
+/// Security of the network we host
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApAuth {
+    /// Works with the oldest phones
+    #[default]
+    Wpa2,
+    Wpa3,
+    /// WPA3 where the client supports it, WPA2 otherwise
+    Wpa2Wpa3,
+    /// No password at all
+    Open,
+}
+
+impl From<ApAuth> for AuthMethod {
+    fn from(auth: ApAuth) -> Self {
+        match auth {
+            ApAuth::Wpa2 => AuthMethod::WPA2Personal,
+            ApAuth::Wpa3 => AuthMethod::WPA3Personal,
+            ApAuth::Wpa2Wpa3 => AuthMethod::WPA2WPA3Personal,
+            ApAuth::Open => AuthMethod::None,
+        }
+    }
+}
+
+/// The network we host, anything left out keeps its default
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApConfig {
+    pub ssid: String,
+    pub password: String,
+    pub auth: ApAuth,
+}
+
+impl Default for ApConfig {
+    fn default() -> Self {
+        Self {
+            ssid: "Dominacao".to_string(),
+            password: "sandidominacao".to_string(),
+            auth: ApAuth::default(),
+        }
+    }
+}
+
+impl ApConfig {
+    /// Catch what the driver would reject, or silently fall back to an open network on
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.ssid.is_empty() || self.ssid.len() > 32 {
+            return Err(anyhow::anyhow!("SSID must be 1 to 32 bytes"));
+        }
+
+        match self.auth {
+            ApAuth::Open if !self.password.is_empty() => {
+                Err(anyhow::anyhow!("An open network can't have a password"))
+            }
+            ApAuth::Open => Ok(()),
+            ApAuth::Wpa2 | ApAuth::Wpa3 | ApAuth::Wpa2Wpa3 => {
+                if (8..=63).contains(&self.password.len()) {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("Password must be 8 to 63 characters"))
+                }
+            }
+        }
+    }
+}
+
+/// Why joining a network didn't work out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    /// SSID or password the driver can't take
+    InvalidCredentials(&'static str),
+    /// No network with that SSID in range
+    NotFound,
+    /// The network is there but didn't let us in, most likely a wrong password
+    AuthFailed,
+    /// Anything else the driver reported
+    Failed(String),
+}
+
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinError::InvalidCredentials(reason) => f.write_str(reason),
+            JoinError::NotFound => f.write_str("Network not found"),
+            JoinError::AuthFailed => f.write_str("Network refused the password"),
+            JoinError::Failed(err) => write!(f, "Failed to join the network: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Driver config to join `ssid`, checked up front so bad input is an error and not a panic
+pub fn client_configuration(
+    ssid: &str,
+    password: &str,
+) -> Result<ClientConfiguration, JoinError> {
+    if ssid.is_empty() {
+        return Err(JoinError::InvalidCredentials("SSID can't be empty"));
+    }
+    std::result::Result::Ok(ClientConfiguration {
+        ssid: ssid
+            .try_into()
+            .map_err(|_| JoinError::InvalidCredentials("SSID must be at most 32 bytes"))?,
+        password: password
+            .try_into()
+            .map_err(|_| JoinError::InvalidCredentials("Password must be at most 64 bytes"))?,
+        auth_method: if password.is_empty() {
+            AuthMethod::None
+        } else {
+            AuthMethod::default()
+        },
+        ..Default::default()
+    })
+}
+
+/// What the radio should be doing
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum WifiMode {
+    /// Host our own network
+    AccessPoint(ApConfig),
+    /// Join an existing network
+    Client {
+        ssid: String,
+        password: String,
+        /// Time server to use instead of `DEFAULT_NTP_SERVER`
+        #[serde(default)]
+        ntp_server: Option<String>,
+    },
+}
+
+/// A phone or laptop associated with the network we host
+#[derive(Debug, Clone, Serialize)]
+pub struct StationInfo {
+    /// `aa:bb:cc:dd:ee:ff`
+    pub mac: String,
+    /// `None` until it got a DHCP lease from us
+    pub ip: Option<Ipv4Addr>,
+    pub rssi: i8,
+}
 
 pub struct Wifi {
     wifi: AsyncWifi<EspWifi<'static>>,
+    /// Running while we're a client, there's no internet to sync from as an access point
+    sntp: Option<EspSntp<'static>>,
+    ntp_server: String,
+    /// Network we host, kept to fall back on when joining another fails
+    ap: ApConfig,
+    /// The clock was set by SNTP at some point since boot, it stays right from then on
+    clock_synced: AtomicBool,
 }
 
 impl Debug for Wifi {
@@ -15,36 +189,95 @@ impl Debug for Wifi {
 
 impl Wifi {
     pub fn init(wifi: AsyncWifi<EspWifi<'static>>) -> Self {
-        Self { wifi }
+        Self {
+            wifi,
+            sntp: None,
+            ntp_server: DEFAULT_NTP_SERVER.to_string(),
+            ap: ApConfig::default(),
+            clock_synced: false.into(),
+        }
+    }
+
+    /// Wall clock time, `None` until SNTP set the clock. Without a sync since boot the clock
+    /// counts from 1970, only a client connection to a network with internet can fix that.
+    pub fn now(&self) -> Option<SystemTime> {
+        let synced = self.clock_synced.load(Ordering::Relaxed)
+            || self
+                .sntp
+                .as_ref()
+                .is_some_and(|sntp| sntp.get_sync_status() == SyncStatus::Completed);
+        if !synced {
+            return None;
+        }
+        self.clock_synced.store(true, Ordering::Relaxed);
+        Some(SystemTime::now())
+    }
+
+    /// Use `server` for the next time sync
+    pub fn set_ntp_server(&mut self, server: String) {
+        self.ntp_server = server;
+    }
+
+    /// Sync the clock in the background, a failure only costs us real timestamps
+    fn start_sntp(&mut self) {
+        let mut conf = SntpConf::default();
+        conf.servers[0] = &self.ntp_server;
+        match EspSntp::new(&conf) {
+            std::result::Result::Ok(sntp) => self.sntp = Some(sntp),
+            Err(err) => log::warn!(
+                target: "wifi",
+                "Failed to start SNTP with {}: {err}",
+                self.ntp_server
+            ),
+        }
     }
 
+    /// Fails with a `JoinError` telling bad credentials, a network out of range and a refused
+    /// password apart
     pub async fn client_mode<S: AsRef<str>>(&mut self, ssid: S, password: S) -> anyhow::Result<()> {
+        let ssid = ssid.as_ref();
+        let client = client_configuration(ssid, password.as_ref())?;
+        self.sntp = None;
         self.wifi.stop().await?;
 
-        let config = esp_idf_svc::wifi::Configuration::Client(ClientConfiguration {
-            ssid: ssid.as_ref().try_into().unwrap(),
-            password: password.as_ref().try_into().unwrap(),
-            ..Default::default()
-        });
+        let config = esp_idf_svc::wifi::Configuration::Client(client);
 
         self.wifi.set_configuration(&config)?;
 
         self.wifi.start().await?;
 
-        self.wifi.connect().await?;
+        // A failed connect doesn't say why, looking for the network first tells the two apart
+        let in_range = self
+            .wifi
+            .scan()
+            .await?
+            .iter()
+            .any(|ap| ap.ssid.as_str() == ssid);
+        if !in_range {
+            return Err(JoinError::NotFound.into());
+        }
+
+        if let Err(err) = self.wifi.connect().await {
+            log::warn!(target: "wifi", "Failed to connect to {ssid}: {err}");
+            return Err(JoinError::AuthFailed.into());
+        }
 
         self.wifi.wait_netif_up().await?;
 
+        self.start_sntp();
+
         Ok(())
     }
 
-    pub async fn ap_mode(&mut self) -> anyhow::Result<()> {
+    pub async fn ap_mode(&mut self, ap: &ApConfig) -> anyhow::Result<()> {
+        ap.validate()?;
+        self.sntp = None;
         self.wifi.stop().await?;
 
         let config = esp_idf_svc::wifi::Configuration::AccessPoint(AccessPointConfiguration {
-            ssid: "Dominacao".try_into().unwrap(),
-            password: "sandidominacao".try_into().unwrap(),
-            auth_method: esp_idf_svc::wifi::AuthMethod::WPA2Personal,
+            ssid: ap.ssid.as_str().try_into().unwrap(),
+            password: ap.password.as_str().try_into().unwrap(),
+            auth_method: ap.auth.into(),
             ..Default::default()
         });
 
@@ -52,6 +285,84 @@ impl Wifi {
 
         self.wifi.start().await?;
 
+        self.ap = ap.clone();
+
+        Ok(())
+    }
+
+    /// Host the network we last hosted again, so the device stays reachable
+    pub async fn fall_back_to_ap(&mut self) -> anyhow::Result<()> {
+        let ap = self.ap.clone();
+        self.ap_mode(&ap).await
+    }
+
+    pub async fn set_mode(&mut self, mode: &WifiMode) -> anyhow::Result<()> {
+        match mode {
+            WifiMode::AccessPoint(ap) => self.ap_mode(ap).await,
+            WifiMode::Client {
+                ssid,
+                password,
+                ntp_server,
+            } => {
+                if let Some(server) = ntp_server {
+                    self.set_ntp_server(server.clone());
+                }
+                self.client_mode(ssid, password).await
+            }
+        }
+    }
+
+    /// Devices associated with the network we host, empty while we're a client
+    pub fn connected_stations(&self) -> anyhow::Result<Vec<StationInfo>> {
+        let hosting = matches!(
+            self.wifi.get_configuration()?,
+            Configuration::AccessPoint(_) | Configuration::Mixed(..)
+        );
+        if !hosting {
+            return Ok(vec![]);
+        }
+
+        let mut stations = wifi_sta_list_t::default();
+        esp!(unsafe { esp_wifi_ap_get_sta_list(&mut stations) })?;
+        let mut leases = wifi_sta_mac_ip_list_t::default();
+        esp!(unsafe { esp_wifi_ap_get_sta_list_with_ip(&stations, &mut leases) })?;
+
+        let count = stations.num.max(0) as usize;
+        let stations = stations.sta.iter().take(count).map(|sta| {
+            let ip = leases
+                .sta
+                .iter()
+                .take(leases.num.max(0) as usize)
+                .find(|lease| lease.mac == sta.mac)
+                // lwIP keeps the address in network order
+                .map(|lease| Ipv4Addr::from(lease.ip.addr.to_le_bytes()))
+                .filter(|ip| !ip.is_unspecified());
+            StationInfo {
+                mac: sta
+                    .mac
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(":"),
+                ip,
+                rssi: sta.rssi,
+            }
+        });
+        Ok(stations.collect())
+    }
+
+    /// Toggle low-power mode: modem sleep and a reduced TX power.
+    /// With BT enabled the modem can't fully leave power save, so "off" means min modem sleep.
+    pub fn set_power_save(&mut self, enabled: bool) -> anyhow::Result<()> {
+        let (ps_type, tx_power) = if enabled {
+            (wifi_ps_type_t_WIFI_PS_MAX_MODEM, LOW_TX_POWER)
+        } else {
+            (wifi_ps_type_t_WIFI_PS_MIN_MODEM, FULL_TX_POWER)
+        };
+
+        esp!(unsafe { esp_wifi_set_ps(ps_type) })?;
+        esp!(unsafe { esp_wifi_set_max_tx_power(tx_power) })?;
+
         Ok(())
     }
 }
@@ -0,0 +1,793 @@
+use std::{ffi::CStr, time::Duration};
+
+use esp_idf_svc::{
+    bt::BdAddr,
+    sys::{
+        esp_get_free_heap_size, esp_get_idf_version, esp_timer_get_time,
+        heap_caps_get_largest_free_block, MALLOC_CAP_8BIT,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets,
+    app::{
+        millis, AppClient, BelowHeldTime, BoardRole, ConfigErrors, FieldError, GameConfig,
+        GameResult, NoGameRunning, NotArmed, NotReady, StorageStats, Team, ARM_WINDOW,
+    },
+    hardware::{
+        bt::{bd_addr_str, AudioConfig, BtError},
+        buzzer::Melodies,
+        wifi::{self, ApConfig, JoinError, WifiMode},
+    },
+    infra::{
+        logging,
+        server::{self, Body, HttpServer, Json, Query, Response, Sink},
+    },
+};
+
+pub fn register(server: &mut HttpServer) {
+    server.get_bare("/ping", || respond(ping()));
+    server.get("/health", || respond(health()));
+    server.get("/system", || respond(system()));
+    server.get("/version", || respond(version()));
+    server.get("/role", || respond(role()));
+    server.post("/role", |req: RoleBody| respond(set_role(req)));
+    server.get("/scores", || respond(scores()));
+    server.get("/games/history", || respond(game_history()));
+    server.get("/game/state", || respond(game_state()));
+    server.get("/game/owner-history", || respond(owner_history()));
+    server.post_query("/game/arm", |_| respond(game_arm()));
+    server.delete("/game/arm", || respond(game_disarm()));
+    server.post_query("/game/start", |query| respond(game_start(query)));
+    server.post("/game/roll-call", |req: Toggle| respond(set_roll_call(req)));
+    server.get("/game/recover", || respond(game_recover()));
+    server.post_query("/game/resume", |_| respond(game_resume()));
+    server.post("/game/owner", |req: OwnerRequest| respond(set_owner(req)));
+    server.post("/game/adjust", |req: AdjustTimeRequest| respond(adjust_time(req)));
+    server.post_query("/game/end", |query| respond(game_end(query)));
+    server.post("/game/forfeit", |req: ForfeitRequest| respond(game_forfeit(req)));
+    server.get("/game/last", || respond(last_game()));
+    server.get("/game/config", || respond(game_config()));
+    // A whole config, as GET /game/config gives it, is past the default body cap
+    server.post_with_limit("/game/config", 1024, |req: GameConfig| respond(set_game_config(req)));
+    server.patch("/game/config", |req: AdjustConfigRequest| respond(adjust_game_config(req)));
+    server.post_with_limit("/game/configure", 1024, |req: GameConfig| {
+        respond(configure_game(req))
+    });
+    server.get("/game/can-start", || respond(game_can_start()));
+    server.post_query("/audio/test", |query| respond(audio_test(query)));
+    server.post("/audio/play", |req: PlayRequest| respond(audio_play(req)));
+    server.get("/audio/format", || respond(audio_format()));
+    server.get("/audio/capacity", || respond(audio_capacity()));
+    server.get("/audio/tuning", || respond(audio_tuning()));
+    server.post("/audio/tuning", |req: AudioConfig| respond(set_audio_tuning(req)));
+    server.post_raw("/audio/stream", |body| respond(audio_stream(body)));
+    server.post("/audio/mute", |req: Toggle| respond(set_audio_muted(req)));
+    server.post("/audio/attract", |req: Toggle| respond(set_attract_mode(req)));
+    server.get("/buzzer/melodies", || respond(melodies()));
+    // Two melodies of `MAX_NOTES` notes with some room for whitespace
+    server.post_with_limit("/buzzer/melodies", 1024, |req: Melodies| respond(set_melodies(req)));
+    // SSID and passphrase alone can take up to 96 bytes
+    server.post_with_limit("/wifi/mode", 256, |req: WifiMode| respond(set_wifi_mode(req)));
+    server.post_with_limit("/wifi/ap", 256, |req: ApConfig| respond(set_wifi_ap(req)));
+    server.post_with_limit("/wifi/join", 256, |req: JoinRequest| respond(wifi_join(req)));
+    server.get("/wifi/join", || respond(wifi_join_result()));
+    server.get("/wifi/stations", || respond(wifi_stations()));
+    server.get_streamed("/logs", "text/plain; charset=utf-8", recent_logs);
+    server.get("/log/level", || respond(log_levels()));
+    server.post("/log/level", |req: LogLevelRequest| respond(set_log_level(req)));
+    server.get("/log/access", || respond(access_log()));
+    server.post("/log/access", |req: Toggle| respond(set_access_log(req)));
+    server.get("/bt/devices", || respond(bt_devices()));
+    server.delete("/bt/devices", || respond(bt_clear_devices()));
+    server.get("/bt/bonded", || respond(bt_bonded()));
+    server.delete_param("/bt/bonded", |addr| respond(bt_forget(addr)));
+    server.post("/bt/scan", |req: ScanRequest| respond(bt_scan(req)));
+    server.post("/bt/connect", |req: ConnectRequest| respond(bt_connect(req)));
+    server.post("/power/idle-timeout", |req: IdleTimeout| respond(set_idle_timeout(req)));
+    server.post("/bt/suspend-when-idle", |req: Toggle| respond(bt_suspend_when_idle(req)));
+    server.post("/bt/name-retries", |req: NameRetries| respond(bt_name_retries(req)));
+}
+
+/// Turn a fallible handler result into a response, errors become a 500
+fn respond(result: anyhow::Result<Response>) -> Response {
+    result.unwrap_or_else(|err| Response::error(500, err.to_string()))
+}
+
+#[derive(Serialize)]
+struct Pong {
+    /// Microseconds since boot
+    t: i64,
+}
+
+/// Link quality probe. The client times the round trip itself: note the time, GET /ping,
+/// subtract once it answers. A few in a row smooth out Wi-Fi jitter, `t` going up by far
+/// less than the RTT means the time was lost on the network rather than on the device.
+/// Never goes through the app loop, so a busy game can't slow it down.
+fn ping() -> anyhow::Result<Response> {
+    let pong = Pong {
+        t: unsafe { esp_timer_get_time() },
+    };
+    Ok(Json::new(&pong)?.into())
+}
+
+fn health() -> anyhow::Result<Response> {
+    let health = AppClient::get().health()?;
+    Ok(Json::new(&health)?.into())
+}
+
+#[derive(Serialize)]
+struct System {
+    #[serde(flatten)]
+    storage: StorageStats,
+    free_heap: u32,
+    /// Seconds since boot
+    uptime_secs: i64,
+}
+
+fn system() -> anyhow::Result<Response> {
+    let system = System {
+        storage: AppClient::get().storage_stats()?,
+        free_heap: unsafe { esp_get_free_heap_size() },
+        uptime_secs: unsafe { esp_timer_get_time() } / 1_000_000,
+    };
+    Ok(Json::new(&system)?.into())
+}
+
+#[derive(Serialize)]
+struct Version {
+    version: &'static str,
+    git_hash: &'static str,
+    /// Unix time the firmware was built at
+    built_at_secs: u64,
+    idf_version: String,
+}
+
+/// Which firmware this board runs, e.g. to confirm an update took
+fn version() -> anyhow::Result<Response> {
+    // ESP-IDF hands out a static, nul terminated string
+    let idf_version = unsafe { CStr::from_ptr(esp_get_idf_version()) };
+
+    let version = Version {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        built_at_secs: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+        idf_version: idf_version.to_string_lossy().into_owned(),
+    };
+    Ok(Json::new(&version)?.into())
+}
+
+#[derive(Serialize, Deserialize)]
+struct RoleBody {
+    role: BoardRole,
+}
+
+/// Role this board booted as, which front-end it serves
+fn role() -> anyhow::Result<Response> {
+    let role = AppClient::get().role()?;
+    Ok(Json::new(&RoleBody { role })?.into())
+}
+
+/// Serve `role`'s front-end from the next boot on
+fn set_role(req: RoleBody) -> anyhow::Result<Response> {
+    AppClient::get().set_role(req.role)?;
+    Ok(Response::accepted())
+}
+
+fn scores() -> anyhow::Result<Response> {
+    let scores = AppClient::get().scores()?;
+    Ok(Json::new(&scores)?.into())
+}
+
+/// Bootstrap payload for the UI, the granular routes and `/ws/scores` keep it current
+fn game_state() -> anyhow::Result<Response> {
+    let snapshot = AppClient::get().snapshot()?;
+    Ok(Json::new(&snapshot)?.into())
+}
+
+/// Ownership timeline of the current or last game, for a control-over-time bar
+fn owner_history() -> anyhow::Result<Response> {
+    let spans = AppClient::get().owner_history()?;
+    Ok(Json::new(&spans)?.into())
+}
+
+#[derive(Serialize)]
+struct Armed {
+    /// How long `/game/start` will be accepted for
+    armed_for_ms: u64,
+}
+
+/// First half of starting a game, `/game/start` is refused without it
+fn game_arm() -> anyhow::Result<Response> {
+    AppClient::get().arm()?;
+    let armed = Armed {
+        armed_for_ms: millis::to_u64(&ARM_WINDOW),
+    };
+    Ok(Json::new(&armed)?.into())
+}
+
+fn game_disarm() -> anyhow::Result<Response> {
+    AppClient::get().disarm()?;
+    Ok(Response::ok())
+}
+
+fn set_roll_call(req: Toggle) -> anyhow::Result<Response> {
+    AppClient::get().set_roll_call(req.enabled)?;
+    Ok(Response::ok())
+}
+
+/// `?force=true` starts without waiting for the roll call
+fn game_start(query: Query) -> anyhow::Result<Response> {
+    let client = AppClient::get();
+    if client.live_state()?.active {
+        return Ok(Response::status(409, "A game is already running"));
+    }
+    match client.start_game(query.get("force") == Some("true")) {
+        Ok(()) => Ok(Response::ok()),
+        Err(err) if err.is::<NotArmed>() => Ok(Response::status(409, "Not armed")),
+        Err(err) if err.is::<NotReady>() => Ok(Response::status(409, "Teams not ready")),
+        Err(err) => Err(err),
+    }
+}
+
+/// Recap of the session, the last finished games oldest first
+fn game_history() -> anyhow::Result<Response> {
+    let history = AppClient::get().game_history()?;
+    Ok(Json::new(&history)?.into())
+}
+
+/// The last finished game, 404 before the first one
+fn last_game() -> anyhow::Result<Response> {
+    match AppClient::get().last_game()? {
+        Some(game) => Ok(Json::new(&game)?.into()),
+        None => Ok(Response::status(404, "No game played yet")),
+    }
+}
+
+/// Game cut short by a power loss, 404 if there's none
+fn game_recover() -> anyhow::Result<Response> {
+    match AppClient::get().recoverable_game()? {
+        Some(game) => Ok(Json::new(&game)?.into()),
+        None => Ok(Response::status(404, "No game to recover")),
+    }
+}
+
+fn game_resume() -> anyhow::Result<Response> {
+    if !AppClient::get().resume_game()? {
+        return Ok(Response::status(404, "No game to recover"));
+    }
+    Ok(Response::ok())
+}
+
+#[derive(Deserialize)]
+struct OwnerRequest {
+    /// `null` leaves the point neutral
+    team: Option<Team>,
+}
+
+fn set_owner(req: OwnerRequest) -> anyhow::Result<Response> {
+    match AppClient::get().set_owner(req.team) {
+        Ok(()) => Ok(Response::ok()),
+        Err(err) if err.is::<NoGameRunning>() => Ok(Response::status(409, "No game is running")),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Deserialize)]
+struct AdjustTimeRequest {
+    team: Team,
+    /// Negative for a penalty
+    delta_secs: i32,
+}
+
+/// Penalty or bonus on a team's held time, answers with the winner if that ended the game
+fn adjust_time(req: AdjustTimeRequest) -> anyhow::Result<Response> {
+    match AppClient::get().adjust_time(req.team, req.delta_secs) {
+        Ok(winner) => {
+            let ended = GameEnded {
+                winner,
+                draw: false,
+            };
+            Ok(Json::new(&ended)?.into())
+        }
+        Err(err) if err.is::<NoGameRunning>() => Ok(Response::status(409, "No game is running")),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Serialize)]
+struct GameEnded {
+    winner: Option<Team>,
+    /// Decided on held time with both teams level
+    draw: bool,
+}
+
+impl From<GameResult> for GameEnded {
+    fn from(result: GameResult) -> Self {
+        Self {
+            winner: result.winner(),
+            draw: result == GameResult::Draw,
+        }
+    }
+}
+
+/// `?declare=true` hands the win to the current leader, `?winner=red|blue` to a given team,
+/// neither ends the game without a winner
+fn game_end(query: Query) -> anyhow::Result<Response> {
+    let client = AppClient::get();
+
+    let result = if query.get("declare") == Some("true") {
+        client.end_and_declare()
+    } else {
+        let winner = match query.get("winner").map(str::parse::<Team>).transpose() {
+            Ok(winner) => winner,
+            Err(err) => return Ok(Response::error(400, err.to_string())),
+        };
+        client
+            .end_game(winner)
+            .map(|_| winner.map_or(GameResult::NoWinner, GameResult::Won))
+    };
+
+    match result {
+        Ok(result) => Ok(Json::new(&GameEnded::from(result))?.into()),
+        Err(err) if err.is::<NoGameRunning>() => Ok(Response::status(409, "No game is running")),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Deserialize)]
+struct ForfeitRequest {
+    loser: Team,
+}
+
+/// A team left, the referee hands the game to the other one
+fn game_forfeit(req: ForfeitRequest) -> anyhow::Result<Response> {
+    match AppClient::get().forfeit(req.loser) {
+        Ok(winner) => {
+            let ended = GameEnded {
+                winner: Some(winner),
+                draw: false,
+            };
+            Ok(Json::new(&ended)?.into())
+        }
+        Err(err) if err.is::<NoGameRunning>() => Ok(Response::status(409, "No game is running")),
+        Err(err) => Err(err),
+    }
+}
+
+fn game_config() -> anyhow::Result<Response> {
+    let config = AppClient::get().game_config()?;
+    Ok(Json::new(&config)?.into())
+}
+
+fn set_game_config(config: GameConfig) -> anyhow::Result<Response> {
+    let errors = config.validate();
+    if !errors.is_empty() {
+        return invalid_config(errors);
+    }
+    if !AppClient::get().set_game_config(config)? {
+        return Ok(Response::status(409, "Can't change the rules mid-game"));
+    }
+    Ok(Response::ok())
+}
+
+/// `ApiError` with every field that failed validation
+#[derive(Serialize)]
+struct InvalidConfig {
+    error: &'static str,
+    status: u16,
+    fields: Vec<FieldError>,
+}
+
+fn invalid_config(fields: Vec<FieldError>) -> anyhow::Result<Response> {
+    let body = InvalidConfig {
+        error: "Invalid game config",
+        status: 422,
+        fields,
+    };
+    let response: Response = Json::new(&body)?.into();
+    Ok(response.with_status(422))
+}
+
+#[derive(Deserialize)]
+struct AdjustConfigRequest {
+    time_to_win_ms: u64,
+    /// Allow a target at or below what a team already held, ending the game right away
+    #[serde(default)]
+    force: bool,
+}
+
+/// Mid-game correction of `time_to_win`, answers with the winner if that ended the game.
+/// 422 like `configure_game` for zero or past a day, 409 at or below a team's held time
+/// without `force`.
+fn adjust_game_config(req: AdjustConfigRequest) -> anyhow::Result<Response> {
+    let result = AppClient::get()
+        .adjust_time_to_win(Duration::from_millis(req.time_to_win_ms), req.force);
+
+    match result {
+        Ok(winner) => {
+            let ended = GameEnded {
+                winner,
+                draw: false,
+            };
+            Ok(Json::new(&ended)?.into())
+        }
+        Err(err) => match err.downcast_ref::<FieldError>() {
+            Some(field) => invalid_config(vec![*field]),
+            None if err.is::<BelowHeldTime>() => Ok(Response::error(409, err.to_string())),
+            None => Err(err),
+        },
+    }
+}
+
+/// All-or-nothing counterpart of `/game/config`, validated instead of clamped and saved
+fn configure_game(config: GameConfig) -> anyhow::Result<Response> {
+    match AppClient::get().configure_game(config) {
+        Ok(true) => Ok(Response::ok()),
+        Ok(false) => Ok(Response::status(409, "Can't change the rules mid-game")),
+        Err(err) => match err.downcast::<ConfigErrors>() {
+            Ok(errors) => invalid_config(errors.0),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+fn game_can_start() -> anyhow::Result<Response> {
+    let preconditions = AppClient::get().start_preconditions()?;
+    Ok(Json::new(&preconditions)?.into())
+}
+
+fn audio_test(query: Query) -> anyhow::Result<Response> {
+    let team = match query.get("team") {
+        Some(team) => match team.parse::<Team>() {
+            Ok(team) => Some(team),
+            Err(err) => return Ok(Response::error(400, err.to_string())),
+        },
+        None => None,
+    };
+
+    if !AppClient::get().play_test_sound(team)? {
+        return Ok(Response::status(409, "No speaker connected"));
+    }
+    Ok(Response::ok())
+}
+
+#[derive(Deserialize)]
+struct PlayRequest {
+    /// Path of a WAV file in the bundled UI, e.g. `sounds/horn.wav`
+    asset: String,
+}
+
+/// Play a sound shipped with the UI bundle, which doubles as a sound library
+fn audio_play(req: PlayRequest) -> anyhow::Result<Response> {
+    let Some(wav) = server::bundled_file(&req.asset) else {
+        return Ok(Response::status(404, "No such asset"));
+    };
+    let pcm = match assets::wav_pcm(wav) {
+        Ok(pcm) => pcm,
+        Err(err) => return Ok(Response::error(422, err.to_string())),
+    };
+    if !AppClient::get().play_clip(pcm)? {
+        return Ok(Response::status(409, "No speaker connected"));
+    }
+    Ok(Response::ok())
+}
+
+fn audio_format() -> anyhow::Result<Response> {
+    let format = AppClient::get().audio_format()?;
+    Ok(Json::new(&format)?.into())
+}
+
+/// Heap an upload has to leave alone, the Wi-Fi and Bluetooth stacks allocate as they go
+const UPLOAD_HEAP_MARGIN: usize = 32 * 1024;
+
+#[derive(Serialize)]
+struct AudioCapacity {
+    /// Largest sound that can be uploaded right now
+    max_upload_bytes: usize,
+    free_heap_bytes: usize,
+}
+
+/// Largest body an upload can be buffered in without running the device out of memory.
+/// A buffer needs one contiguous block, so that's the limit rather than the free total.
+/// Uploads buffered whole should check their Content-Length against this and answer 507 when
+/// over. `/audio/stream` passes the body on as it arrives, so it isn't bound by this.
+fn upload_capacity() -> AudioCapacity {
+    let free = unsafe { esp_get_free_heap_size() } as usize;
+    let largest_block = unsafe { heap_caps_get_largest_free_block(MALLOC_CAP_8BIT) };
+    AudioCapacity {
+        max_upload_bytes: largest_block.min(free.saturating_sub(UPLOAD_HEAP_MARGIN)),
+        free_heap_bytes: free,
+    }
+}
+
+/// For the UI to turn down a sound that won't fit before uploading it
+fn audio_capacity() -> anyhow::Result<Response> {
+    Ok(Json::new(&upload_capacity())?.into())
+}
+
+fn audio_tuning() -> anyhow::Result<Response> {
+    let config = AppClient::get().audio_config()?;
+    Ok(Json::new(&config)?.into())
+}
+
+/// Chunk and prefill sizes for the speaker in use, a fix for underruns without reflashing
+fn set_audio_tuning(config: AudioConfig) -> anyhow::Result<Response> {
+    match AppClient::get().set_audio_config(config) {
+        Ok(()) => Ok(Response::ok()),
+        Err(err) => match err.downcast_ref::<BtError>() {
+            Some(BtError::InvalidAudioConfig(_)) => Ok(Response::error(422, err.to_string())),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Raw 44.1kHz 16 bit stereo PCM, played as it's uploaded.
+/// The server handles one request at a time, nothing else is answered until the upload ends.
+fn audio_stream(body: &mut Body) -> anyhow::Result<Response> {
+    let Some(stream) = AppClient::get().audio_stream()? else {
+        return Ok(Response::status(409, "No speaker connected"));
+    };
+
+    // Small pieces keep the upload in step with the speaker
+    let mut buf = [0u8; 512];
+    loop {
+        let read = body.read(&mut buf)?;
+        if read == 0 {
+            return Ok(Response::ok());
+        }
+        if !stream.write(&buf[..read]) {
+            return Ok(Response::status(409, "Stream cut off by another sound"));
+        }
+    }
+}
+
+fn set_wifi_mode(mode: WifiMode) -> anyhow::Result<Response> {
+    // The switch happens after we answer, too late to report a bad config
+    let valid = match &mode {
+        WifiMode::AccessPoint(ap) => ap.validate(),
+        WifiMode::Client { ssid, password, .. } => wifi::client_configuration(ssid, password)
+            .map(|_| ())
+            .map_err(Into::into),
+    };
+    if let Err(err) = valid {
+        return Ok(Response::error(400, err.to_string()));
+    }
+    AppClient::get().set_wifi_mode(mode)?;
+    Ok(Response::accepted())
+}
+
+/// Host a network with these settings, shorthand for `/wifi/mode` with `access_point`
+fn set_wifi_ap(ap: ApConfig) -> anyhow::Result<Response> {
+    set_wifi_mode(WifiMode::AccessPoint(ap))
+}
+
+/// Last lines logged, as plain text. Written out a line at a time, all of them at once
+/// would take a big block of heap for a moment.
+fn recent_logs(sink: &mut Sink) -> anyhow::Result<()> {
+    logging::write_recent(|line| {
+        sink.write(line.as_bytes())?;
+        sink.write(b"\n")
+    })
+}
+
+#[derive(Deserialize)]
+struct JoinRequest {
+    ssid: String,
+    #[serde(default)]
+    password: String,
+    ntp_server: Option<String>,
+}
+
+/// Join a network, shorthand for `/wifi/mode` with `client`. Only bad credentials are
+/// caught here, how the join went is on `GET /wifi/join` once the device is reachable again.
+fn wifi_join(req: JoinRequest) -> anyhow::Result<Response> {
+    set_wifi_mode(WifiMode::Client {
+        ssid: req.ssid,
+        password: req.password,
+        ntp_server: req.ntp_server,
+    })
+}
+
+/// Who is on the network we host, to tell a phone that never joined from a page that won't load
+fn wifi_stations() -> anyhow::Result<Response> {
+    let stations = AppClient::get().wifi_stations()?;
+    Ok(Json::new(&stations)?.into())
+}
+
+/// Outcome of the last join: 200 if it worked, 400 for bad credentials, 401 for a refused
+/// password and 404 for a network out of range. A failed join hosts our own network again.
+fn wifi_join_result() -> anyhow::Result<Response> {
+    let Some(err) = AppClient::get().wifi_join_error()? else {
+        return Ok(Response::ok());
+    };
+    let status = match err {
+        JoinError::InvalidCredentials(_) => 400,
+        JoinError::AuthFailed => 401,
+        JoinError::NotFound => 404,
+        JoinError::Failed(_) => 502,
+    };
+    Ok(Response::error(status, err.to_string()))
+}
+
+fn log_levels() -> anyhow::Result<Response> {
+    Ok(Json::new(&logging::levels())?.into())
+}
+
+#[derive(Deserialize)]
+struct LogLevelRequest {
+    level: String,
+    /// Only change this tag, all of them when absent
+    tag: Option<String>,
+}
+
+fn set_log_level(req: LogLevelRequest) -> anyhow::Result<Response> {
+    if let Err(err) = logging::set_level(req.tag.as_deref(), &req.level) {
+        return Ok(Response::error(400, err.to_string()));
+    }
+    Ok(Json::new(&logging::levels())?.into())
+}
+
+/// `{"enabled": true}`, for on/off settings
+#[derive(Serialize, Deserialize)]
+struct Toggle {
+    enabled: bool,
+}
+
+fn access_log() -> anyhow::Result<Response> {
+    let state = Toggle {
+        enabled: server::access_log_enabled(),
+    };
+    Ok(Json::new(&state)?.into())
+}
+
+fn set_access_log(req: Toggle) -> anyhow::Result<Response> {
+    server::set_access_log(req.enabled);
+    Ok(Response::ok())
+}
+
+fn bt_devices() -> anyhow::Result<Response> {
+    let devices = AppClient::get().bt_devices()?;
+    Ok(Json::new(&devices)?.into())
+}
+
+fn bt_clear_devices() -> anyhow::Result<Response> {
+    AppClient::get().bt_clear_devices()?;
+    Ok(Response::ok())
+}
+
+#[derive(Serialize)]
+struct BondedDevice {
+    #[serde(with = "bd_addr_str")]
+    addr: BdAddr,
+}
+
+fn bt_bonded() -> anyhow::Result<Response> {
+    let bonded: Vec<_> = AppClient::get()
+        .bt_bonded_devices()?
+        .into_iter()
+        .map(|addr| BondedDevice { addr })
+        .collect();
+    Ok(Json::new(&bonded)?.into())
+}
+
+/// `addr` as in the URL, colons may come percent-encoded
+fn bt_forget(addr: &str) -> anyhow::Result<Response> {
+    let addr = match bd_addr_str::parse(&addr.replace("%3A", ":").replace("%3a", ":")) {
+        Ok(addr) => addr,
+        Err(err) => return Ok(Response::error(400, err.to_string())),
+    };
+    if !AppClient::get().bt_forget_device(addr)? {
+        return Ok(Response::status(404, "Not paired with that device"));
+    }
+    Ok(Response::ok())
+}
+
+fn default_scan_secs() -> u64 {
+    10
+}
+
+fn default_scan_max() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+struct ScanRequest {
+    #[serde(default = "default_scan_secs")]
+    duration_secs: u64,
+    #[serde(default = "default_scan_max")]
+    max: usize,
+    /// Include phones, laptops and anything else that isn't a speaker
+    #[serde(default)]
+    all: bool,
+    /// Forget what earlier scans found first
+    #[serde(default)]
+    fresh: bool,
+}
+
+/// Another scan or connect already has the radio, that's a 409 rather than a failure
+fn bt_busy(err: anyhow::Error) -> anyhow::Result<Response> {
+    match err.downcast_ref::<BtError>() {
+        Some(BtError::Busy) => Ok(Response::status(409, "Bluetooth is busy")),
+        _ => Err(err),
+    }
+}
+
+fn bt_scan(req: ScanRequest) -> anyhow::Result<Response> {
+    let result = AppClient::get().bt_discover(
+        Duration::from_secs(req.duration_secs),
+        req.max,
+        !req.all,
+        req.fresh,
+    );
+    match result {
+        Ok(devices) => Ok(Json::new(&devices)?.into()),
+        Err(err) => bt_busy(err),
+    }
+}
+
+#[derive(Deserialize)]
+struct ConnectRequest {
+    #[serde(with = "bd_addr_str")]
+    addr: BdAddr,
+}
+
+fn bt_connect(req: ConnectRequest) -> anyhow::Result<Response> {
+    match AppClient::get().bt_connect(req.addr) {
+        Ok(()) => Ok(Response::ok()),
+        Err(err) => bt_busy(err),
+    }
+}
+
+/// Soft mute, the Bluetooth stream keeps running on silence
+fn set_audio_muted(req: Toggle) -> anyhow::Result<Response> {
+    AppClient::get().set_audio_muted(req.enabled)?;
+    Ok(Response::ok())
+}
+
+/// Jingle every few minutes while no game is running
+fn set_attract_mode(req: Toggle) -> anyhow::Result<Response> {
+    AppClient::get().set_attract_mode(req.enabled)?;
+    Ok(Response::ok())
+}
+
+/// Capture melodies the buzzer plays per team
+fn melodies() -> anyhow::Result<Response> {
+    let melodies = AppClient::get().melodies()?;
+    Ok(Json::new(&melodies)?.into())
+}
+
+fn set_melodies(melodies: Melodies) -> anyhow::Result<Response> {
+    if let Err(err) = melodies.validate() {
+        return Ok(Response::error(422, err.to_string()));
+    }
+    AppClient::get().set_melodies(melodies)?;
+    Ok(Response::ok())
+}
+
+fn bt_suspend_when_idle(req: Toggle) -> anyhow::Result<Response> {
+    AppClient::get().bt_suspend_when_idle(req.enabled)?;
+    Ok(Response::ok())
+}
+
+/// `{"idle_timeout_secs": 300}`, `null` keeps the device from ever going to sleep on its own
+#[derive(Deserialize)]
+struct IdleTimeout {
+    idle_timeout_secs: Option<u64>,
+}
+
+fn set_idle_timeout(req: IdleTimeout) -> anyhow::Result<Response> {
+    AppClient::get().set_idle_timeout(req.idle_timeout_secs.map(Duration::from_secs))?;
+    Ok(Response::ok())
+}
+
+#[derive(Deserialize)]
+struct NameRetries {
+    retries: usize,
+}
+
+fn bt_name_retries(req: NameRetries) -> anyhow::Result<Response> {
+    AppClient::get().bt_name_retries(req.retries)?;
+    Ok(Response::ok())
+}
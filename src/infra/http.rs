@@ -0,0 +1,373 @@
+//! Answering a request short of talking to the HTTP server: responses, query strings, JSON
+//! bodies and the routes of bundled files. Nothing in here needs the IDF, so it builds and is
+//! tested on the host.
+
+use serde::Serialize;
+
+/// What a JSON route answers to `body`, everything past reading the request. Doesn't touch
+/// the HTTP server, so a route's handling can be driven with plain bytes.
+pub fn handle_json<B: for<'a> serde::Deserialize<'a>, F: Fn(B) -> Response>(
+    body: &[u8],
+    handler: F,
+) -> Response {
+    // Same as `serde_json::from_slice`, keeping track of where in the body it failed
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    match serde_path_to_error::deserialize::<_, B>(&mut deserializer) {
+        Ok(body) => match deserializer.end() {
+            Ok(()) => handler(body),
+            Err(err) => invalid_body(".", err),
+        },
+        Err(err) => {
+            let path = err.path().to_string();
+            invalid_body(&path, err.into_inner())
+        }
+    }
+}
+
+/// `ApiError` for a body that couldn't be read, with where it went wrong so a form can point
+/// at the culprit
+#[derive(Serialize)]
+struct InvalidBody<'a> {
+    error: &'a str,
+    status: u16,
+    /// Path to the offending field, e.g. `config.time_to_win_ms` or `melodies.red[2]`, `null`
+    /// when the body as a whole is wrong
+    field: Option<String>,
+    /// What the field should have been, as serde puts it, e.g. `u64`
+    expected: Option<&'a str>,
+    /// Where in the body, 1-based
+    line: usize,
+    column: usize,
+}
+
+/// 400 for a body that isn't JSON, 422 for JSON that doesn't have the expected shape. `path`
+/// is where the error was hit, `.` for the top level.
+fn invalid_body(path: &str, err: serde_json::Error) -> Response {
+    let status = match err.classify() {
+        serde_json::error::Category::Data => 422,
+        _ => 400,
+    };
+    // The message ends in the position, which is reported separately
+    let message = err.to_string();
+    let message = match message.rsplit_once(" at line ") {
+        Some((message, _)) => message,
+        None => &message,
+    };
+    // A missing field is reported on the object it's missing from
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next());
+    // Broken JSON can stop the parser before it read a key, that part of the path is a `?`
+    let path = path.trim_end_matches(".?").trim_end_matches('?');
+    let field = match (path, missing) {
+        ("" | ".", None) => None,
+        ("" | ".", Some(missing)) => Some(missing.to_string()),
+        (path, Some(missing)) => Some(format!("{path}.{missing}")),
+        (path, None) => Some(path.to_string()),
+    };
+    let body = InvalidBody {
+        error: message,
+        status,
+        field,
+        expected: message.split_once(", expected ").map(|(_, expected)| expected),
+        line: err.line(),
+        column: err.column(),
+    };
+    Response {
+        status_code: status,
+        content_type: "application/json".to_string(),
+        // Can't fail, it's strings and numbers
+        body: ResponseBody::String(serde_json::to_string(&body).unwrap_or_default()),
+    }
+}
+
+/// Query string parameters, `?a=1&b=2`
+pub struct Query(Vec<(String, String)>);
+
+impl Query {
+    pub fn from_uri(uri: &str) -> Self {
+        let params = uri
+            .split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or("")
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (key.to_string(), value.to_string())
+            })
+            .collect();
+
+        Self(params)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// `uri` without its `token` query parameter, for logging a request without its secret
+pub fn without_token(uri: &str) -> String {
+    let Some((path, query)) = uri.split_once('?') else {
+        return uri.to_string();
+    };
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && pair.split('=').next() != Some("token"))
+        .collect();
+    if kept.is_empty() {
+        path.to_string()
+    } else {
+        format!("{path}?{}", kept.join("&"))
+    }
+}
+
+/// URL a bundled file is served at: forward slashes whatever the build host used, with
+/// anything a browser would percent-encode in a path encoded the same way. That's what's left
+/// of a path segment's characters (RFC 3986 `pchar`) once the unreserved set, sub-delims, `:`
+/// and `@` are taken out, the IDF server matches routes against the URI as sent.
+#[cfg(any(feature = "embedded-ui", test))]
+pub fn route_for_path(path: &std::path::Path) -> String {
+    let path = path.to_string_lossy();
+    let mut route = String::new();
+    // Split by hand, `Path` on the target wouldn't treat a Windows build host's `\` as a separator
+    for segment in path.split(['/', '\\']).filter(|s| !s.is_empty() && *s != ".") {
+        route.push('/');
+        for byte in segment.bytes() {
+            match byte {
+                // Unreserved
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~'
+                // Sub-delims
+                | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'='
+                | b':' | b'@' => route.push(byte as char),
+                _ => route.push_str(&format!("%{byte:02X}")),
+            }
+        }
+    }
+
+    if route.is_empty() {
+        route.push('/');
+    }
+    route
+}
+
+pub enum ResponseBody {
+    String(String),
+    StaticString(&'static str),
+    Bytes(&'static [u8])
+}
+
+pub struct Response {
+    pub(crate) status_code: u16,
+    pub(crate) content_type: String,
+    pub(crate) body: ResponseBody,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            body: ResponseBody::StaticString(""),
+            content_type: "application/json".to_string(),
+            status_code: 200,
+        }
+    }
+
+    /// Request taken, carried out in the background
+    pub fn accepted() -> Self {
+        Self {
+            status_code: 202,
+            ..Self::ok()
+        }
+    }
+
+    pub fn with_status(self, status_code: u16) -> Self {
+        Self {
+            status_code,
+            ..self
+        }
+    }
+
+    /// Error response with a fixed message, see `ApiError`
+    pub fn status(status_code: u16, message: &'static str) -> Self {
+        ApiError::response(status_code, message)
+    }
+
+    /// Error response, see `ApiError`
+    pub fn error(status_code: u16, message: String) -> Self {
+        ApiError::response(status_code, &message)
+    }
+
+    pub fn body(&self) -> &[u8] {
+        match &self.body {
+            ResponseBody::StaticString(payload) => {
+                payload.as_bytes()
+            },
+            ResponseBody::String(payload) => {
+                payload.as_bytes()
+            },
+            ResponseBody::Bytes(payload) => {
+                payload
+            }
+        }
+    }
+}
+
+/// Body of every error response: `{"error": "No game is running", "status": 409}`
+#[derive(Serialize)]
+pub struct ApiError<'a> {
+    pub error: &'a str,
+    pub status: u16,
+}
+
+impl ApiError<'_> {
+    pub fn response(status: u16, message: &str) -> Response {
+        let body = ApiError {
+            error: message,
+            status,
+        };
+        Response {
+            status_code: status,
+            content_type: "application/json".to_string(),
+            // Can't fail, it's a string and a number
+            body: ResponseBody::String(serde_json::to_string(&body).unwrap_or_default()),
+        }
+    }
+}
+
+pub struct Json(String);
+
+impl Json {
+    pub fn new<T: serde::Serialize>(value: &T) -> anyhow::Result<Self> {
+        Ok(Self(serde_json::to_string(value)?))
+    }
+}
+
+impl From<Json> for Response {
+    fn from(json: Json) -> Self {
+        Response {
+            status_code: 200,
+            content_type: "application/json".to_string(),
+            body: ResponseBody::String(json.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Outer {
+        inner: Inner,
+    }
+
+    #[derive(Deserialize)]
+    struct Inner {
+        count: u32,
+    }
+
+    fn answer(body: &str) -> (u16, Value) {
+        let response = handle_json(body.as_bytes(), |outer: Outer| {
+            Json::new(&outer.inner.count).unwrap().into()
+        });
+        let json = serde_json::from_slice(response.body()).unwrap();
+        (response.status_code, json)
+    }
+
+    #[test]
+    fn token_is_left_out_of_logged_uris() {
+        assert_eq!(without_token("/logs?token=secret"), "/logs");
+        assert_eq!(without_token("/game/end?token=secret&winner=red"), "/game/end?winner=red");
+        assert_eq!(without_token("/game/end?winner=red&token="), "/game/end?winner=red");
+        assert_eq!(without_token("/scores?tokens=1"), "/scores?tokens=1");
+        assert_eq!(without_token("/scores"), "/scores");
+    }
+
+    #[test]
+    fn bundled_paths_become_routes() {
+        let route = |path: &str| route_for_path(std::path::Path::new(path));
+        assert_eq!(route("index.html"), "/index.html");
+        assert_eq!(route("./assets//app-Xy_9~.js"), "/assets/app-Xy_9~.js");
+        assert_eq!(route("assets\\logo.svg"), "/assets/logo.svg");
+        assert_eq!(route(""), "/");
+        assert_eq!(route("."), "/");
+    }
+
+    #[test]
+    fn bundled_paths_keep_what_browsers_send_as_is() {
+        let route = |path: &str| route_for_path(std::path::Path::new(path));
+        assert_eq!(route("img/logo(1)+v2@2x.png"), "/img/logo(1)+v2@2x.png");
+        assert_eq!(route("a!b$c&d'e*f,g;h=i:j"), "/a!b$c&d'e*f,g;h=i:j");
+        assert_eq!(route("my file.svg"), "/my%20file.svg");
+        assert_eq!(route("100%.txt"), "/100%25.txt");
+        assert_eq!(route("what?#.txt"), "/what%3F%23.txt");
+        assert_eq!(route("ação.png"), "/a%C3%A7%C3%A3o.png");
+    }
+
+    #[test]
+    fn valid_body_reaches_the_handler() {
+        let (status, json) = answer(r#"{"inner": {"count": 3}}"#);
+        assert_eq!(status, 200);
+        assert_eq!(json, 3);
+    }
+
+    #[test]
+    fn handler_status_goes_out_as_is() {
+        let response = handle_json(br#"{"inner": {"count": 3}}"#, |_: Outer| {
+            Response::status(409, "No game is running")
+        });
+        assert_eq!(response.status_code, 409);
+    }
+
+    #[test]
+    fn broken_json_is_a_400_with_its_position() {
+        let (status, json) = answer("{\n  \"inner\": {\"count\": 3,");
+        assert_eq!(status, 400);
+        assert_eq!(json["field"], "inner");
+        assert_eq!(json["line"], 2);
+        assert!(json["column"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn json_that_isnt_an_object_is_a_400_without_a_field() {
+        let (status, json) = answer("nope");
+        assert_eq!(status, 400);
+        assert_eq!(json["field"], Value::Null);
+    }
+
+    #[test]
+    fn trailing_garbage_is_a_400() {
+        let (status, _) = answer(r#"{"inner": {"count": 3}} x"#);
+        assert_eq!(status, 400);
+    }
+
+    #[test]
+    fn wrong_type_is_a_422_naming_the_field() {
+        let (status, json) = answer(r#"{"inner": {"count": "three"}}"#);
+        assert_eq!(status, 422);
+        assert_eq!(json["field"], "inner.count");
+        assert_eq!(json["expected"], "u32");
+        assert_eq!(json["status"], 422);
+    }
+
+    #[test]
+    fn missing_field_is_a_422_naming_the_field() {
+        let (status, json) = answer(r#"{"inner": {}}"#);
+        assert_eq!(status, 422);
+        assert_eq!(json["field"], "inner.count");
+        assert_eq!(json["error"], "missing field `count`");
+    }
+
+    #[test]
+    fn missing_top_level_field_has_no_dot() {
+        let (status, json) = answer("{}");
+        assert_eq!(status, 422);
+        assert_eq!(json["field"], "inner");
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::VecDeque, str::FromStr, sync::Mutex};
+
+use anyhow::anyhow;
+use esp_idf_svc::{log::EspLogger, sys::esp_log_timestamp};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+static LOGGER: EspLogger = EspLogger::new();
+
+/// Lines kept for `GET /logs`
+pub const RECENT_LINES: usize = 200;
+
+/// Last `RECENT_LINES` lines logged, oldest first
+struct Recent {
+    lines: VecDeque<String>,
+    /// Lines logged since boot, the front one is number `total - lines.len()`
+    total: u64,
+}
+
+static RECENT: Mutex<Recent> = Mutex::new(Recent {
+    lines: VecDeque::new(),
+    total: 0,
+});
+
+/// Logs to the serial console through `EspLogger` and keeps a copy of the last lines,
+/// to read them over HTTP in the field without a cable
+struct RecordingLogger;
+
+static RECORDING_LOGGER: RecordingLogger = RecordingLogger;
+
+impl Log for RecordingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        LOGGER.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !LOGGER.enabled(record.metadata()) {
+            return;
+        }
+        LOGGER.log(record);
+
+        let line = format!(
+            "({}) {} {}: {}",
+            unsafe { esp_log_timestamp() },
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // A panic while holding the lock must not silence logging for good
+        let mut recent = RECENT.lock().unwrap_or_else(|err| err.into_inner());
+        if recent.lines.len() == RECENT_LINES {
+            recent.lines.pop_front();
+        }
+        recent.lines.push_back(line);
+        recent.total += 1;
+    }
+
+    fn flush(&self) {
+        LOGGER.flush();
+    }
+}
+
+/// Tags whose level can be changed at runtime. Log sites pass one of these as `target:`, the
+/// default target is the module path and no runtime level applies to it.
+pub const TAGS: &[&str] = &["bt", "wifi", "game", "http"];
+
+/// Levels changed at runtime, tags not in here are at the boot default
+static OVERRIDES: Mutex<Vec<(&'static str, LevelFilter)>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Serialize)]
+pub struct TagLevel {
+    pub tag: &'static str,
+    pub level: String,
+}
+
+pub fn init() -> anyhow::Result<()> {
+    log::set_logger(&RECORDING_LOGGER).map_err(|err| anyhow!("Failed to set logger: {err}"))?;
+    LOGGER.initialize();
+    Ok(())
+}
+
+/// Set the level of one allowlisted tag, or all of them when `tag` is `None`
+pub fn set_level(tag: Option<&str>, level: &str) -> anyhow::Result<()> {
+    let level = LevelFilter::from_str(level).map_err(|_| anyhow!("Unknown log level '{level}'"))?;
+    let tags: Vec<&'static str> = match tag {
+        Some(tag) => vec![TAGS
+            .iter()
+            .copied()
+            .find(|t| *t == tag)
+            .ok_or_else(|| anyhow!("Unknown log tag '{tag}', expected one of {TAGS:?}"))?],
+        None => TAGS.to_vec(),
+    };
+
+    let mut overrides = OVERRIDES.lock().expect("Poisoned");
+    for tag in tags {
+        LOGGER.set_target_level(tag, level)?;
+        overrides.retain(|(t, _)| *t != tag);
+        overrides.push((tag, level));
+    }
+
+    // The global filter has to let the most verbose tag through
+    let max = overrides
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(LOGGER.get_max_level(), LevelFilter::max);
+    log::set_max_level(max);
+
+    Ok(())
+}
+
+pub fn levels() -> Vec<TagLevel> {
+    let overrides = OVERRIDES.lock().expect("Poisoned");
+    TAGS.iter()
+        .map(|tag| {
+            let level = overrides
+                .iter()
+                .find(|(t, _)| t == tag)
+                .map(|(_, level)| *level)
+                .unwrap_or_else(|| LOGGER.get_max_level());
+            TagLevel {
+                tag,
+                level: level.as_str().to_lowercase(),
+            }
+        })
+        .collect()
+}
+
+/// Line number `n` counted from boot, or the oldest one kept if that already rotated out
+fn recent_from(n: u64) -> Option<(u64, String)> {
+    let recent = RECENT.lock().unwrap_or_else(|err| err.into_inner());
+    let first = recent.total - recent.lines.len() as u64;
+    let n = n.max(first);
+    let line = recent.lines.get((n - first) as usize)?.clone();
+    Some((n, line))
+}
+
+/// Hand the recently logged lines to `write` one by one, oldest first. Only one line is
+/// copied at a time and the log isn't locked while `write` runs, it's free to log itself.
+pub fn write_recent(mut write: impl FnMut(&str) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    // Stop at what was there to begin with, or a chatty writer never gets to the end
+    let end = RECENT.lock().unwrap_or_else(|err| err.into_inner()).total;
+    let mut next = 0;
+    while let Some((n, line)) = recent_from(next) {
+        if n >= end {
+            break;
+        }
+        write(&line)?;
+        next = n + 1;
+    }
+    Ok(())
+}
@@ -1 +1,5 @@
-pub mod server;
\ No newline at end of file
+pub mod api;
+pub mod http;
+pub mod logging;
+pub mod server;
+pub mod ws;
@@ -1,30 +1,319 @@
+use std::{
+    ffi::CStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
 use anyhow::Ok;
 use esp_idf_svc::{
-    http::{headers::content_type, server::EspHttpServer},
+    http::{
+        headers::content_type,
+        Method,
+        server::{ws::EspHttpWsConnection, EspHttpServer},
+        ws::FrameType,
+    },
+    sys::{httpd_req_get_hdr_value_len, httpd_req_get_hdr_value_str, EspError, ESP_OK},
     io::{Read, Write},
 };
+#[cfg(feature = "embedded-ui")]
 use include_dir::{Dir, include_dir};
 
+use crate::app::BoardRole;
+
+#[cfg(feature = "embedded-ui")]
+use super::http::route_for_path;
+use super::http::{handle_json, without_token, ResponseBody};
+pub use super::http::{Json, Query, Response};
+
+#[cfg(feature = "embedded-ui")]
 static SVELTE_BUILD: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/web-ui/dist");
 
-const MAX_PAYLOAD_LEN: usize = 128;
+/// Body cap for routes that don't ask for a bigger one, plenty for small JSON commands
+const DEFAULT_MAX_PAYLOAD_LEN: usize = 128;
+
+pub type RequestHook = Arc<dyn Fn() + Send + Sync>;
+
+/// Sockets long-lived streams (WebSockets, uploads, streamed downloads) can never take, so
+/// the referee's control requests can still connect with a few dashboards open. That's a
+/// free socket, not a quick answer: every handler runs on the one httpd task, so a request
+/// arriving during an upload or a `/logs` dump waits until it's done.
+const RESERVED_CONTROL_SLOTS: usize = 1;
+
+/// WebSocket close code for "try again later", what a 503 is to plain HTTP
+const WS_CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+
+/// WebSocket close code for a connection the server won't take, here a missing or wrong token
+const WS_CLOSE_POLICY_VIOLATION: u16 = 1008;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Sockets the server keeps open at once. The IDF uses 3 of `CONFIG_LWIP_MAX_SOCKETS`
+    /// itself, this has to fit in the rest.
+    pub max_connections: usize,
+    /// 80 unless something else on the board needs it. Phones that open the UI from the
+    /// network's sign-in prompt only ever try 80.
+    pub port: u16,
+    /// Stack of the server task, every handler runs on it
+    pub stack_size: usize,
+    /// Routes that can be registered, each file of the embedded UI takes one too
+    pub max_uri_handlers: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let idf = esp_idf_svc::http::server::Configuration::default();
+        Self {
+            max_connections: 7,
+            port: idf.http_port,
+            stack_size: idf.stack_size,
+            // The IDF's 32 is already less than the API alone registers
+            max_uri_handlers: 96,
+        }
+    }
+}
+
+/// Who a route is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Reading the game, e.g. players following the score
+    Spectator,
+    /// Changing anything, the referees
+    Admin,
+}
+
+/// Secrets the roles are unlocked with. A role without one is open to anyone, with neither
+/// set the API is as open as it always was.
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    pub spectator: Option<String>,
+    /// Also unlocks everything a spectator can do
+    pub admin: Option<String>,
+}
+
+impl Tokens {
+    /// Whether a request showing `presented` may call a route for `role`
+    fn allows(&self, role: Role, presented: Option<&str>) -> bool {
+        let matches = |secret: &Option<String>| match (secret, presented) {
+            (Some(secret), Some(presented)) => constant_time_eq(secret, presented),
+            _ => false,
+        };
+        match role {
+            Role::Spectator => {
+                self.spectator.is_none() || matches(&self.spectator) || matches(&self.admin)
+            }
+            Role::Admin => self.admin.is_none() || matches(&self.admin),
+        }
+    }
+
+    /// `None` if the request may go ahead, the 401 to answer with otherwise. The token comes
+    /// as `Authorization: Bearer <token>`, or `?token=` for links that can't set headers.
+    fn check(&self, role: Role, authorization: Option<&str>, uri: &str) -> Option<Response> {
+        let query = Query::from_uri(uri);
+        let presented = authorization
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .or_else(|| query.get("token"));
+        if self.allows(role, presented) {
+            return None;
+        }
+        Some(Response::status(401, "Missing or wrong token"))
+    }
+}
+
+/// Compare without bailing out at the first difference, so timing doesn't leak the secret
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[derive(Default)]
+struct Streams {
+    /// Sessions of open WebSockets
+    websockets: Vec<i32>,
+    /// Uploads and streamed downloads in progress
+    transfers: usize,
+}
+
+impl Streams {
+    fn len(&self) -> usize {
+        self.websockets.len() + self.transfers
+    }
+}
+
+/// Counts the sockets held by long-lived streams against their share of the server
+struct StreamSlots {
+    open: Mutex<Streams>,
+    max: usize,
+}
+
+impl StreamSlots {
+    fn new(config: &ServerConfig) -> Self {
+        Self {
+            open: Mutex::new(Streams::default()),
+            max: config.max_connections.saturating_sub(RESERVED_CONTROL_SLOTS),
+        }
+    }
+
+    fn open_ws(&self, session: i32) -> bool {
+        let mut open = self.open.lock().expect("Poisoned");
+        if open.len() >= self.max {
+            return false;
+        }
+        open.websockets.push(session);
+        true
+    }
+
+    fn is_open_ws(&self, session: i32) -> bool {
+        self.open.lock().expect("Poisoned").websockets.contains(&session)
+    }
+
+    fn close_ws(&self, session: i32) {
+        self.open
+            .lock()
+            .expect("Poisoned")
+            .websockets
+            .retain(|open| *open != session);
+    }
+
+    fn open_transfer(self: &Arc<Self>) -> Option<TransferSlot> {
+        let mut open = self.open.lock().expect("Poisoned");
+        if open.len() >= self.max {
+            return None;
+        }
+        open.transfers += 1;
+        Some(TransferSlot(self.clone()))
+    }
+}
+
+/// Held for as long as an upload or a streamed download runs
+struct TransferSlot(Arc<StreamSlots>);
+
+impl Drop for TransferSlot {
+    fn drop(&mut self) {
+        self.0.open.lock().expect("Poisoned").transfers -= 1;
+    }
+}
+
+/// Off by default, a line per request adds up quickly with the scoreboard polling
+static ACCESS_LOG: AtomicBool = AtomicBool::new(false);
+
+/// Log method, path, status and handling time of every HTTP request
+pub fn set_access_log(enabled: bool) {
+    ACCESS_LOG.store(enabled, Ordering::Relaxed);
+}
+
+pub fn access_log_enabled() -> bool {
+    ACCESS_LOG.load(Ordering::Relaxed)
+}
+
+/// A request being timed for the access log. The `token` query parameter is left out, the
+/// log is readable with a spectator token and must not hand out the admin one.
+struct Access {
+    method: &'static str,
+    uri: String,
+    started: Instant,
+}
+
+impl Access {
+    /// `None` while access logging is off, so nothing gets copied or timed
+    fn begin(method: &'static str, uri: &str) -> Option<Self> {
+        access_log_enabled().then(|| Self {
+            method,
+            uri: without_token(uri),
+            started: Instant::now(),
+        })
+    }
+
+    /// `status` is `None` when the handler failed before answering, the server sends a 500 then
+    fn end(access: Option<Self>, status: Option<u16>) {
+        let Some(access) = access else {
+            return;
+        };
+        let status = status.unwrap_or(500);
+        log::info!(
+            target: "http",
+            "{} {} {} {}ms",
+            access.method,
+            access.uri,
+            status,
+            access.started.elapsed().as_millis()
+        );
+    }
+}
+
+/// Served at `/` when there's no UI, so the root still shows the device is up
+const STATUS_PAGE: &str = concat!(
+    "<!doctype html><html><head><meta charset=\"utf-8\">",
+    "<meta name=\"viewport\" content=\"width=device-width\"><title>Dominacao</title></head>",
+    "<body><h1>Dominacao is up</h1><p>Firmware ",
+    env!("CARGO_PKG_VERSION"),
+    ", built without the web UI.</p><ul>",
+    "<li><a href=\"/health\">/health</a></li>",
+    "<li><a href=\"/version\">/version</a></li>",
+    "<li><a href=\"/game/state\">/game/state</a></li>",
+    "<li><a href=\"/logs\">/logs</a></li>",
+    "</ul></body></html>",
+);
+
+fn register_status_page(server: &mut HttpServer) {
+    server.get_public("/", || Response {
+        status_code: 200,
+        content_type: "text/html".into(),
+        body: ResponseBody::StaticString(STATUS_PAGE),
+    });
+}
+
+/// A file from the bundled UI by its path in `dist/`, e.g. `sounds/horn.wav`
+#[cfg(feature = "embedded-ui")]
+pub fn bundled_file(path: &str) -> Option<&'static [u8]> {
+    SVELTE_BUILD
+        .get_file(path.trim_start_matches('/'))
+        .map(|file| file.contents())
+}
+
+#[cfg(not(feature = "embedded-ui"))]
+pub fn bundled_file(_path: &str) -> Option<&'static [u8]> {
+    None
+}
+
+/// Without the `embedded-ui` feature there is no bundle to serve, only the API is up
+#[cfg(not(feature = "embedded-ui"))]
+pub fn load_svelte(server: &mut HttpServer, _role: BoardRole) {
+    register_status_page(server);
+}
+
+/// Serve `role`'s front-end from its subdirectory of the bundle (`dist/master/`,
+/// `dist/slave/`) at `/`. A bundle without one is served whole, whatever the role.
+#[cfg(feature = "embedded-ui")]
+pub fn load_svelte(server: &mut HttpServer, role: BoardRole) {
+    let root = match SVELTE_BUILD.get_dir(role.ui_dir()) {
+        Some(dir) => dir,
+        None => {
+            log::info!(target: "http", "No {role:?} UI in the bundle, serving all of it");
+            &SVELTE_BUILD
+        }
+    };
 
-pub fn load_svelte(server: &mut HttpServer) {
     // Serve index.html at `/` as the main entrypoint
-    if let Some(index) = SVELTE_BUILD.get_file("index.html") {
+    if let Some(index) = root.get_file(root.path().join("index.html")) {
         let contents = index.contents();
-        server.get("/", move || Response {
+        server.get_public("/", move || Response {
             status_code: 200,
             content_type: "text/html".into(),
             body: ResponseBody::Bytes(contents),
         });
+    } else {
+        log::warn!(target: "http", "The bundled UI has no index.html, serving a status page at /");
+        register_status_page(server);
     }
 
-    // Recursively register all files in the dist folder
-    fn register_dir(dir: &Dir<'static>, server: &mut HttpServer) {
+    // Recursively register all files in the served folder
+    fn register_dir(root: &Dir<'static>, dir: &Dir<'static>, server: &mut HttpServer) {
         for file in dir.files() {
-            // The file path relative to the root of `dist/`
-            let route = format!("/{}", file.path().display());
+            // The file path relative to the served folder
+            let path = file.path().strip_prefix(root.path()).unwrap_or(file.path());
+            let route = route_for_path(path);
 
             let contents = file.contents();
             let content_type = match file.path().extension().and_then(|s| s.to_str()) {
@@ -40,6 +329,7 @@ pub fn load_svelte(server: &mut HttpServer) {
                 Some("woff2") => "font/woff2",
                 Some("ttf") => "font/ttf",
                 Some("json") => "application/json",
+                Some("wav") => "audio/wav",
                 _ => "application/octet-stream",
             }
             .to_string();
@@ -48,7 +338,7 @@ pub fn load_svelte(server: &mut HttpServer) {
             let contents = contents;
             let content_type = content_type.clone();
 
-            server.get(route, move || Response {
+            server.get_public(route, move || Response {
                 status_code: 200,
                 content_type: content_type.clone(),
                 body: ResponseBody::Bytes(contents),
@@ -57,47 +347,298 @@ pub fn load_svelte(server: &mut HttpServer) {
 
         // Recurse into subdirectories
         for subdir in dir.dirs() {
-            register_dir(subdir, server);
+            register_dir(root, subdir, server);
         }
     }
 
-    register_dir(&SVELTE_BUILD, server);
+    register_dir(root, root, server);
 }
 
 pub struct HttpServer {
     esp_http_server: EspHttpServer<'static>,
+    on_request: Option<RequestHook>,
+    tokens: Arc<Tokens>,
+    streams: Arc<StreamSlots>,
 }
 
 impl HttpServer {
     pub fn new() -> Self {
+        Self::with_config(&ServerConfig::default())
+    }
+
+    pub fn with_config(config: &ServerConfig) -> Self {
         let server = EspHttpServer::new(&esp_idf_svc::http::server::Configuration {
+            http_port: config.port,
+            stack_size: config.stack_size,
+            max_uri_handlers: config.max_uri_handlers,
+            max_open_sockets: config.max_connections,
+            // With every socket taken, make room by dropping the least recently used one
+            lru_purge_enable: true,
+            // For routes ending in a path parameter, see `delete_param`
+            uri_match_wildcard: true,
             ..Default::default()
         })
         .unwrap();
 
         Self {
             esp_http_server: server,
+            on_request: None,
+            tokens: Arc::new(Tokens::default()),
+            streams: Arc::new(StreamSlots::new(config)),
         }
     }
+
+    /// Hook invoked at the start of every request.
+    /// Only applies to routes registered after it is set.
+    pub fn on_request<F: Fn() + Send + Sync + 'static>(&mut self, hook: F) -> &mut Self {
+        self.on_request = Some(Arc::new(hook));
+        self
+    }
+
+    /// Lock routes down by `Role`: GETs need the spectator token, everything else the admin
+    /// one. Only applies to routes registered after it is set.
+    pub fn set_tokens(&mut self, tokens: Tokens) -> &mut Self {
+        self.tokens = Arc::new(tokens);
+        self
+    }
+
     pub fn get<S: AsRef<str>, F: Fn() -> Response + Send + Sync + 'static>(
         &mut self,
         url: S,
         handler: F,
+    ) -> &mut Self {
+        self.bodyless_route(Method::Get, "GET", Some(Role::Spectator), url, move |_| handler())
+    }
+
+    /// GET open to anyone whatever the tokens, for the UI's own files
+    pub fn get_public<S: AsRef<str>, F: Fn() -> Response + Send + Sync + 'static>(
+        &mut self,
+        url: S,
+        handler: F,
+    ) -> &mut Self {
+        self.bodyless_route(Method::Get, "GET", None, url, move |_| handler())
+    }
+
+    /// GET without the access log, the `on_request` hook or a token check, for probes that
+    /// have to stay cheap
+    pub fn get_bare<S: AsRef<str>, F: Fn() -> Response + Send + Sync + 'static>(
+        &mut self,
+        url: S,
+        handler: F,
     ) -> &mut Self {
+        self.esp_http_server
+            .fn_handler(url.as_ref(), Method::Get, move |request| {
+                let response = handler();
+                request
+                    .into_response(
+                        response.status_code,
+                        None,
+                        &[content_type(&response.content_type)],
+                    )?
+                    .write(response.body())
+                    .map(|_| ())
+            })
+            .unwrap();
+
+        self
+    }
+
+    /// GET writing its body out as it's produced, sent chunked. For payloads too large to
+    /// build in memory first, an error midway cuts the response short.
+    /// Counts as a stream, 503 when they already hold all the sockets they may.
+    pub fn get_streamed<S, F>(&mut self, url: S, mime: &'static str, handler: F) -> &mut Self
+    where
+        S: AsRef<str>,
+        F: Fn(&mut Sink) -> anyhow::Result<()> + Send + Sync + 'static,
+    {
+        let on_request = self.on_request.clone();
+        let tokens = self.tokens.clone();
+        let streams = self.streams.clone();
+        self.esp_http_server
+            .fn_handler::<anyhow::Error, _>(url.as_ref(), Method::Get, move |request| {
+                let access = Access::begin("GET", request.uri());
+                if let Some(hook) = &on_request {
+                    hook();
+                }
+                let denied =
+                    tokens.check(Role::Spectator, request.header("Authorization"), request.uri());
+                let slot = match denied {
+                    Some(response) => Err(response),
+                    None => streams.open_transfer().ok_or_else(|| {
+                        Response::status(503, "Too many streams open, try again later")
+                    }),
+                };
+                let _slot = match slot {
+                    std::result::Result::Ok(slot) => slot,
+                    Err(response) => {
+                        let result = request
+                            .into_response(
+                                response.status_code,
+                                None,
+                                &[content_type(&response.content_type)],
+                            )
+                            .and_then(|mut resp| resp.write(response.body()))
+                            .map(|_| ())
+                            .map_err(anyhow::Error::from);
+                        Access::end(access, result.is_ok().then_some(response.status_code));
+                        return result;
+                    }
+                };
+                let result = request
+                    .into_response(200, None, &[content_type(mime)])
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut resp| {
+                        let mut write = |buf: &[u8]| Ok(resp.write_all(buf)?);
+                        handler(&mut Sink(&mut write))
+                    });
+                Access::end(access, result.is_ok().then_some(200));
+                result
+            })
+            .unwrap();
+
+        self
+    }
+
+    pub fn delete<S: AsRef<str>, F: Fn() -> Response + Send + Sync + 'static>(
+        &mut self,
+        url: S,
+        handler: F,
+    ) -> &mut Self {
+        self.bodyless_route(Method::Delete, "DELETE", Some(Role::Admin), url, move |_| handler())
+    }
+
+    /// DELETE of `{prefix}/{param}`, the handler gets `param` as sent, not percent-decoded
+    pub fn delete_param<F: Fn(&str) -> Response + Send + Sync + 'static>(
+        &mut self,
+        prefix: &str,
+        handler: F,
+    ) -> &mut Self {
+        let url = format!("{prefix}/*");
+        let start = prefix.len() + 1;
+        self.bodyless_route(Method::Delete, "DELETE", Some(Role::Admin), url, move |uri| {
+            let path = uri.split('?').next().unwrap_or_default();
+            handler(path.get(start..).unwrap_or_default())
+        })
+    }
+
+    /// Route that doesn't look at the body, `name` is the method as logged and the handler
+    /// gets the URI. `role` is who may call it, `None` for anyone.
+    fn bodyless_route<S: AsRef<str>, F: Fn(&str) -> Response + Send + Sync + 'static>(
+        &mut self,
+        method: Method,
+        name: &'static str,
+        role: Option<Role>,
+        url: S,
+        handler: F,
+    ) -> &mut Self {
+        let on_request = self.on_request.clone();
+        let tokens = self.tokens.clone();
         self.esp_http_server
             .fn_handler(
                 url.as_ref(),
-                esp_idf_svc::http::Method::Get,
+                method,
                 move |request| {
-                    let response = handler();
-                    request
+                    let access = Access::begin(name, request.uri());
+                    if let Some(hook) = &on_request {
+                        hook();
+                    }
+                    let denied = role.and_then(|role| {
+                        tokens.check(role, request.header("Authorization"), request.uri())
+                    });
+                    let response = denied.unwrap_or_else(|| handler(request.uri()));
+                    let result = request
                         .into_response(
                             response.status_code,
                             None,
                             &[content_type(&response.content_type)],
                         )?
                         .write(response.body())
-                        .map(|_| ())
+                        .map(|_| ());
+                    Access::end(access, result.is_ok().then_some(response.status_code));
+                    result
+                },
+            )
+            .unwrap();
+
+        self
+    }
+
+    /// WebSocket route, for spectators. The handshake is already answered by the time this
+    /// runs, so a connection without the token is closed with 1008 rather than refused, and
+    /// once the share of sockets left to streams is used up new ones are closed with 1013.
+    /// Frames on a connection that was closed like this never reach `handler`.
+    pub fn ws<S: AsRef<str>, F>(&mut self, url: S, handler: F) -> &mut Self
+    where
+        F: for<'a> Fn(&'a mut EspHttpWsConnection) -> Result<(), EspError> + Send + Sync + 'static,
+    {
+        let on_request = self.on_request.clone();
+        let tokens = self.tokens.clone();
+        let streams = self.streams.clone();
+        self.esp_http_server
+            .ws_handler(url.as_ref(), move |conn| {
+                if conn.is_closed() {
+                    streams.close_ws(conn.session());
+                } else if conn.is_new() {
+                    if let Some(hook) = &on_request {
+                        hook();
+                    }
+                    let (uri, authorization) = upgrade_request(conn);
+                    if tokens.check(Role::Spectator, authorization.as_deref(), &uri).is_some() {
+                        log::warn!(target: "http", "Turning away a WebSocket without a token");
+                        let mut close = WS_CLOSE_POLICY_VIOLATION.to_be_bytes().to_vec();
+                        close.extend_from_slice(b"Missing or wrong token");
+                        return conn.send(FrameType::Close, &close);
+                    }
+                    if !streams.open_ws(conn.session()) {
+                        log::warn!(
+                            target: "http",
+                            "Too many streams open, turning a WebSocket away"
+                        );
+                        let mut close = WS_CLOSE_TRY_AGAIN_LATER.to_be_bytes().to_vec();
+                        close.extend_from_slice(b"Server busy");
+                        return conn.send(FrameType::Close, &close);
+                    }
+                } else if !streams.is_open_ws(conn.session()) {
+                    // Turned away above, whatever it still sends before closing is ignored
+                    return std::result::Result::Ok(());
+                }
+                handler(conn)
+            })
+            .unwrap();
+
+        self
+    }
+
+    /// POST route driven by the query string only, the body is ignored
+    pub fn post_query<S: AsRef<str>, F: Fn(Query) -> Response + Send + Sync + 'static>(
+        &mut self,
+        url: S,
+        handler: F,
+    ) -> &mut Self {
+        let on_request = self.on_request.clone();
+        let tokens = self.tokens.clone();
+        self.esp_http_server
+            .fn_handler(
+                url.as_ref(),
+                esp_idf_svc::http::Method::Post,
+                move |request| {
+                    let access = Access::begin("POST", request.uri());
+                    if let Some(hook) = &on_request {
+                        hook();
+                    }
+                    let response = tokens
+                        .check(Role::Admin, request.header("Authorization"), request.uri())
+                        .unwrap_or_else(|| handler(Query::from_uri(request.uri())));
+                    let result = request
+                        .into_response(
+                            response.status_code,
+                            None,
+                            &[content_type(&response.content_type)],
+                        )?
+                        .write(response.body())
+                        .map(|_| ());
+                    Access::end(access, result.is_ok().then_some(response.status_code));
+                    result
                 },
             )
             .unwrap();
@@ -114,87 +655,239 @@ impl HttpServer {
         url: S,
         handler: F,
     ) -> &mut Self {
+        self.post_with_limit(url, DEFAULT_MAX_PAYLOAD_LEN, handler)
+    }
+
+    /// POST route reading the body itself as it arrives, for uploads that can't be buffered.
+    /// Needs a Content-Length, the IDF server can't read chunked bodies.
+    /// Counts as a stream, 503 when they already hold all the sockets they may.
+    pub fn post_raw<S: AsRef<str>, F: Fn(&mut Body) -> Response + Send + Sync + 'static>(
+        &mut self,
+        url: S,
+        handler: F,
+    ) -> &mut Self {
+        let on_request = self.on_request.clone();
+        let tokens = self.tokens.clone();
+        let streams = self.streams.clone();
         self.esp_http_server
             .fn_handler::<anyhow::Error, _>(
                 url.as_ref(),
-                esp_idf_svc::http::Method::Post,
+                Method::Post,
                 move |mut request| {
-                    let len = request
-                        .header("Content-Length")
-                        .unwrap_or("0")
-                        .parse::<usize>()?;
-
-                    if len > MAX_PAYLOAD_LEN {
-                        request
-                            .into_status_response(413)?
-                            .write_all("Request too big".as_bytes())?;
-                        return Ok(());
+                    let access = Access::begin("POST", request.uri());
+                    if let Some(hook) = &on_request {
+                        hook();
                     }
-
-                    let mut buf = vec![0; len];
-                    request.read_exact(&mut buf)?;
-
-                    let response = handler(serde_json::from_slice::<B>(&buf)?);
-                    request
+                    let denied =
+                        tokens.check(Role::Admin, request.header("Authorization"), request.uri());
+                    let response = match denied {
+                        Some(response) => response,
+                        None => match streams.open_transfer() {
+                            Some(_slot) => {
+                                let mut read = |buf: &mut [u8]| Ok(request.read(buf)?);
+                                handler(&mut Body(&mut read))
+                            }
+                            None => {
+                                Response::status(503, "Too many streams open, try again later")
+                            }
+                        },
+                    };
+                    let result = request
                         .into_response(
                             response.status_code,
                             None,
                             &[content_type(&response.content_type)],
-                        )?
-                        .write(response.body())?;
-                    Ok(())
+                        )
+                        .and_then(|mut resp| resp.write(response.body()))
+                        .map(|_| ())
+                        .map_err(anyhow::Error::from);
+                    Access::end(access, result.is_ok().then_some(response.status_code));
+                    result
                 },
             )
             .unwrap();
 
         self
     }
-}
 
-pub enum ResponseBody {
-    String(String),
-    StaticString(&'static str),
-    Bytes(&'static [u8])
+    /// Like `post`, accepting bodies up to `max_len` bytes instead of the default
+    pub fn post_with_limit<
+        S: AsRef<str>,
+        B: for<'a> serde::Deserialize<'a> + 'static,
+        F: Fn(B) -> Response + Send + Sync + 'static,
+    >(
+        &mut self,
+        url: S,
+        max_len: usize,
+        handler: F,
+    ) -> &mut Self {
+        self.json_route(Method::Post, "POST", url, max_len, handler)
+    }
+
+    /// Partial update with a JSON body, same rules as `post`
+    pub fn patch<
+        S: AsRef<str>,
+        B: for<'a> serde::Deserialize<'a> + 'static,
+        F: Fn(B) -> Response + Send + Sync + 'static,
+    >(
+        &mut self,
+        url: S,
+        handler: F,
+    ) -> &mut Self {
+        self.json_route(Method::Patch, "PATCH", url, DEFAULT_MAX_PAYLOAD_LEN, handler)
+    }
+
+    /// Route taking a JSON body of up to `max_len` bytes, `name` is the method as logged
+    fn json_route<
+        S: AsRef<str>,
+        B: for<'a> serde::Deserialize<'a> + 'static,
+        F: Fn(B) -> Response + Send + Sync + 'static,
+    >(
+        &mut self,
+        method: Method,
+        name: &'static str,
+        url: S,
+        max_len: usize,
+        handler: F,
+    ) -> &mut Self {
+        let on_request = self.on_request.clone();
+        let tokens = self.tokens.clone();
+        self.esp_http_server
+            .fn_handler::<anyhow::Error, _>(
+                url.as_ref(),
+                method,
+                move |mut request| {
+                    let access = Access::begin(name, request.uri());
+                    if let Some(hook) = &on_request {
+                        hook();
+                    }
+                    // Every way out of here reports the status it sent, for the access log
+                    let handler = &handler;
+                    let tokens = &tokens;
+                    let result = (move || -> anyhow::Result<u16> {
+                        let response = 'response: {
+                            let authorization = request.header("Authorization");
+                            if let Some(denied) =
+                                tokens.check(Role::Admin, authorization, request.uri())
+                            {
+                                break 'response denied;
+                            }
+
+                            let chunked = request
+                                .header("Transfer-Encoding")
+                                .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+                            if chunked {
+                                break 'response Response::status(
+                                    411,
+                                    "Chunked bodies are not supported, send a Content-Length",
+                                );
+                            }
+
+                            let body = match request.header("Content-Length") {
+                                Some(len) => {
+                                    let std::result::Result::Ok(len) = len.parse::<usize>() else {
+                                        break 'response Response::status(
+                                            400,
+                                            "Invalid Content-Length",
+                                        );
+                                    };
+                                    if len > max_len {
+                                        None
+                                    } else {
+                                        let mut buf = vec![0; len];
+                                        request.read_exact(&mut buf)?;
+                                        Some(buf)
+                                    }
+                                }
+                                // No length given, take whatever comes until EOF
+                                None => read_to_end_capped(&mut request, max_len)?,
+                            };
+
+                            let Some(buf) = body else {
+                                break 'response Response::status(413, "Request too big");
+                            };
+
+                            handle_json(&buf, handler)
+                        };
+
+                        request
+                            .into_response(
+                                response.status_code,
+                                None,
+                                &[content_type(&response.content_type)],
+                            )?
+                            .write(response.body())?;
+                        Ok(response.status_code)
+                    })();
+                    Access::end(access, result.as_ref().ok().copied());
+                    result.map(|_| ())
+                },
+            )
+            .unwrap();
+
+        self
+    }
 }
 
-pub struct Response {
-    status_code: u16,
-    content_type: String,
-    body: ResponseBody,
+/// Request body handed to `post_raw` handlers
+pub struct Body<'a>(&'a mut dyn FnMut(&mut [u8]) -> anyhow::Result<usize>);
+
+impl Body<'_> {
+    /// Like `Read::read`, `0` once the body is over
+    pub fn read(&mut self, buf: &mut [u8]) -> anyhow::Result<usize> {
+        (self.0)(buf)
+    }
 }
 
-impl Response {
-    pub fn ok() -> Self {
-        Self {
-            body: ResponseBody::StaticString(""),
-            content_type: "application/json".to_string(),
-            status_code: 200,
-        }
+/// Response body handed to `get_streamed` handlers
+pub struct Sink<'a>(&'a mut dyn FnMut(&[u8]) -> anyhow::Result<()>);
+
+impl Sink<'_> {
+    /// Send `buf` on its way, nothing of it is kept
+    pub fn write(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        (self.0)(buf)
     }
+}
 
-    pub fn body(&self) -> &[u8] {
-        match &self.body {
-            ResponseBody::StaticString(payload) => {
-                payload.as_bytes()
-            },
-            ResponseBody::String(payload) => {
-                payload.as_bytes()
-            },
-            ResponseBody::Bytes(payload) => {
-                payload
-            }
+/// URI and `Authorization` header of a new WebSocket's upgrade request, both empty once the
+/// connection is past that
+fn upgrade_request(conn: &EspHttpWsConnection) -> (String, Option<String>) {
+    let EspHttpWsConnection::New(_, req) = conn else {
+        return (String::new(), None);
+    };
+    let req = *req;
+    let name = c"Authorization";
+    // The handshake request stays alive for the handler's first call on the connection
+    unsafe {
+        let uri = CStr::from_ptr((*req).uri.as_ptr()).to_string_lossy().into_owned();
+        let len = httpd_req_get_hdr_value_len(req, name.as_ptr());
+        if len == 0 {
+            return (uri, None);
         }
+        let mut buf = vec![0u8; len + 1];
+        let found =
+            httpd_req_get_hdr_value_str(req, name.as_ptr(), buf.as_mut_ptr().cast(), buf.len());
+        let authorization = (found == ESP_OK)
+            .then(|| CStr::from_bytes_until_nul(&buf).ok())
+            .flatten()
+            .map(|value| value.to_string_lossy().into_owned());
+        (uri, authorization)
     }
 }
 
-pub struct Json(String);
+/// Read until EOF, `None` if the body turns out to be longer than `max`
+fn read_to_end_capped<R: Read>(reader: &mut R, max: usize) -> Result<Option<Vec<u8>>, R::Error> {
+    let mut body = Vec::new();
+    let mut chunk = [0u8; 64];
 
-impl Into<Response> for Json {
-    fn into(self) -> Response {
-        Response {
-            status_code: 200,
-            content_type: "application/json".to_string(),
-            body: ResponseBody::String(self.0),
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            return std::result::Result::Ok(Some(body));
+        }
+        if body.len() + read > max {
+            return std::result::Result::Ok(None);
         }
+        body.extend_from_slice(&chunk[..read]);
     }
 }
@@ -0,0 +1,167 @@
+//! Live score stream over WebSocket, pushed as the app broadcasts state changes: on captures,
+//! game start/end, and whenever a held time crosses a whole second.
+//!
+//! Two encodings of the same data:
+//! - `/ws/scores`: JSON text frames, the `StateUpdate` as broadcast by the app.
+//! - `/ws/scores/bin`: fixed 16 byte little-endian binary frames (see `encode_binary`),
+//!   a fraction of the size for smooth progress bars over a flaky link.
+//!
+//! The first frame on a new connection is the full state, a `/game/state` snapshot on
+//! `/ws/scores` (a superset of the updates' fields), so a late joiner doesn't wait for the
+//! next change to show anything.
+//!
+//! The format is picked by path rather than `Sec-WebSocket-Protocol`: the IDF server can
+//! only echo a single subprotocol fixed per route, which esp-idf-svc doesn't expose, and
+//! browsers drop the connection when an offered subprotocol isn't echoed back.
+
+use std::{
+    sync::{Mutex, Once},
+    time::Duration,
+};
+
+use esp_idf_svc::http::{server::ws::EspHttpWsDetachedSender, ws::FrameType};
+
+use crate::{
+    app::{AppClient, LiveState, StateUpdate, Team},
+    infra::server::HttpServer,
+};
+
+/// Wait before retrying when the app can't be subscribed to
+const SUBSCRIBE_RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Binary,
+}
+
+struct Subscriber {
+    sender: EspHttpWsDetachedSender,
+    format: Format,
+}
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static STREAMER: Once = Once::new();
+
+pub fn register(server: &mut HttpServer) {
+    server.ws("/ws/scores", |conn| {
+        if conn.is_new() {
+            subscribe(conn.create_detached_sender()?, Format::Json);
+        }
+        Ok(())
+    });
+    server.ws("/ws/scores/bin", |conn| {
+        if conn.is_new() {
+            subscribe(conn.create_detached_sender()?, Format::Binary);
+        }
+        Ok(())
+    });
+}
+
+fn subscribe(mut sender: EspHttpWsDetachedSender, format: Format) {
+    // Holding the list while saying hello keeps any update taken after the snapshot from
+    // overtaking it
+    let mut subscribers = SUBSCRIBERS.lock().expect("Poisoned");
+    if let Err(err) = send_hello(&mut sender, format) {
+        log::warn!(target: "http", "Failed to send the state to a new subscriber: {err}");
+    }
+    subscribers.push(Subscriber { sender, format });
+    drop(subscribers);
+    STREAMER.call_once(|| {
+        std::thread::spawn(stream_scores);
+    });
+}
+
+fn stream_scores() {
+    loop {
+        let updates = match AppClient::get().subscribe() {
+            Ok(updates) => updates,
+            Err(err) => {
+                log::error!(target: "http", "Failed to subscribe to state updates: {err}");
+                std::thread::sleep(SUBSCRIBE_RETRY);
+                continue;
+            }
+        };
+
+        // Ends when the app drops us for falling behind, then we resubscribe
+        for update in updates {
+            if SUBSCRIBERS.lock().expect("Poisoned").is_empty() {
+                continue;
+            }
+            send_update(&update);
+        }
+    }
+}
+
+fn send_hello(sender: &mut EspHttpWsDetachedSender, format: Format) -> anyhow::Result<()> {
+    let snapshot = AppClient::get().snapshot()?;
+    match format {
+        Format::Json => {
+            sender.send(FrameType::Text(false), &serde_json::to_vec(&snapshot)?)?;
+        }
+        Format::Binary => {
+            sender.send(FrameType::Binary(false), &encode_binary(&snapshot.live))?;
+        }
+    }
+    Ok(())
+}
+
+fn send_update(update: &StateUpdate) {
+    let json = match serde_json::to_vec(update) {
+        Ok(json) => json,
+        Err(err) => {
+            log::error!(target: "http", "Failed to serialize scores: {err}");
+            return;
+        }
+    };
+    let binary = encode_binary(&update.live);
+
+    // Closed connections fail to send, that's when they get dropped
+    SUBSCRIBERS
+        .lock()
+        .expect("Poisoned")
+        .retain_mut(|subscriber| {
+            let result = match subscriber.format {
+                Format::Json => subscriber.sender.send(FrameType::Text(false), &json),
+                Format::Binary => subscriber.sender.send(FrameType::Binary(false), &binary),
+            };
+            result.is_ok()
+        });
+}
+
+/// Binary frame layout, all little-endian:
+///
+/// | offset | size | field                                  |
+/// |--------|------|----------------------------------------|
+/// | 0      | 4    | red held time, ms (u32)                |
+/// | 4      | 4    | blue held time, ms (u32)               |
+/// | 8      | 1    | owner: 0 none, 1 red, 2 blue           |
+/// | 9      | 1    | flags: bit 0 active, bit 1 locked out  |
+/// | 10     | 2    | red captures (u16)                     |
+/// | 12     | 2    | blue captures (u16)                    |
+/// | 14     | 2    | reserved, zero                         |
+fn encode_binary(state: &LiveState) -> [u8; 16] {
+    let scores = &state.scores;
+    let mut frame = [0u8; 16];
+
+    frame[0..4].copy_from_slice(&clamp_u32(scores.red().as_millis()).to_le_bytes());
+    frame[4..8].copy_from_slice(&clamp_u32(scores.blue().as_millis()).to_le_bytes());
+    frame[8] = match state.owner {
+        None => 0,
+        Some(Team::Red) => 1,
+        Some(Team::Blue) => 2,
+    };
+    frame[9] = (state.active as u8) | ((!scores.lockout_remaining().is_zero() as u8) << 1);
+    frame[10..12].copy_from_slice(&clamp_u16(scores.captures_red()).to_le_bytes());
+    frame[12..14].copy_from_slice(&clamp_u16(scores.captures_blue()).to_le_bytes());
+
+    frame
+}
+
+fn clamp_u32(value: u128) -> u32 {
+    value.min(u32::MAX as u128) as u32
+}
+
+fn clamp_u16(value: u32) -> u16 {
+    value.min(u16::MAX as u32) as u16
+}
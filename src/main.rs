@@ -3,10 +3,18 @@ use esp_idf_svc::{
     eventloop::EspSystemEventLoop, hal::prelude::Peripherals, nvs::EspDefaultNvsPartition, sys::l64a, timer::EspTaskTimerService, wifi::{AsyncWifi, EspWifi}
 };
 
-use crate::{app::{App, AppClient, Team}, hardware::{buttons::InputButton, wifi::Wifi}, infra::server::{HttpServer, load_svelte}};
+use std::{sync::{Arc, Mutex}, time::Instant};
+
+use crate::{app::{App, AppClient, AppConfig, AudioOutput, AudioSink, BoardRole, Team}, hardware::{buttons::{HoldCombo, InputButton}, i2s::I2sAudio, pins::{BUTTON_ACTIVE_LEVEL, BUTTON_DEBOUNCE_MS, BoardPins}, storage::Storage, wifi::Wifi}, infra::{api, logging, server::{HttpServer, Tokens, load_svelte}, ws}};
 use crate::{
-    hardware::bt::BluetoothAudio,
+    hardware::{
+        bt::{BluetoothAudio, DEFAULT_RING_BUFFER_SIZE},
+        buzzer::Buzzer,
+        outputs::GpioSignal,
+    },
 };
+#[cfg(feature = "game-signal")]
+use esp_idf_svc::hal::gpio::OutputPin;
 
 pub mod assets;
 pub mod hardware;
@@ -15,7 +23,7 @@ mod infra;
 
 fn main() -> Result<()> {
     esp_idf_svc::sys::link_patches();
-    esp_idf_svc::log::EspLogger::initialize_default();
+    logging::init()?;
 
     let peripherals = Peripherals::take()?;
     let nvs = EspDefaultNvsPartition::take()?;
@@ -29,17 +37,58 @@ fn main() -> Result<()> {
         wifi_timer,
     )?;
 
-    let red_btn = InputButton::new(peripherals.pins.gpio19, 50)?;
-    let blue_btn = InputButton::new(peripherals.pins.gpio18, 50)?;
+    let pins = BoardPins::take(peripherals.pins);
+    let red_btn = InputButton::new(pins.red_button, BUTTON_DEBOUNCE_MS, BUTTON_ACTIVE_LEVEL)?;
+    let blue_btn = InputButton::new(pins.blue_button, BUTTON_DEBOUNCE_MS, BUTTON_ACTIVE_LEVEL)?;
+    let stop_btn = InputButton::new(pins.stop_button, BUTTON_DEBOUNCE_MS, BUTTON_ACTIVE_LEVEL)?;
     let wifi = Wifi::init(async_wifi);
-    let bt = BluetoothAudio::init(bt_modem, Some(nvs.clone()))?;
-    let app = App::init(wifi, bt);
+    let config = AppConfig::default();
+    let (audio, bt): (Arc<dyn AudioSink>, _) = match config.audio_output {
+        AudioOutput::Bluetooth => {
+            let bt = BluetoothAudio::init(bt_modem, Some(nvs.clone()), DEFAULT_RING_BUFFER_SIZE)?;
+            (bt.clone(), Some(bt))
+        }
+        AudioOutput::I2s => {
+            let i2s = I2sAudio::init(peripherals.i2s0, pins.i2s_bclk, pins.i2s_dout, pins.i2s_ws)?;
+            (Arc::new(i2s), None)
+        }
+    };
+    let buzzer = Buzzer::init(peripherals.ledc.timer0, peripherals.ledc.channel0, pins.buzzer)
+        .inspect_err(|err| log::error!("Failed to start the buzzer: {err}"))
+        .ok();
+    #[cfg(feature = "game-signal")]
+    let signal = GpioSignal::new(pins.game_signal.downgrade_output())
+        .inspect_err(|err| log::error!("Failed to set up the game signal: {err}"))
+        .ok();
+    #[cfg(not(feature = "game-signal"))]
+    let signal: Option<GpioSignal> = None;
+    let combo = Mutex::new(HoldCombo::new(config.combo_hold));
+    // Last level reported to the app for each button, see `AppClient::team_held`
+    let levels = Mutex::new([false; 2]);
+    let storage = Storage::new(nvs.clone(), "game")
+        .inspect_err(|err| log::error!("Game checkpoints are off, NVS unavailable: {err}"))
+        .ok();
+    let app = App::init(wifi, audio, bt, buzzer, signal, storage, config);
     let mut server = HttpServer::new();
+    server.on_request(|| {
+        if AppClient::get().wake().is_err() {
+            log::error!("Failed to register HTTP activity");
+        }
+    });
+    // Baked in at build time, leaving them unset keeps the API open like before
+    server.set_tokens(Tokens {
+        spectator: option_env!("DOMINACAO_SPECTATOR_TOKEN").map(str::to_string),
+        admin: option_env!("DOMINACAO_ADMIN_TOKEN").map(str::to_string),
+    });
 
-    register_routes(&mut server);
+    register_routes(&mut server, app.role());
 
     esp_idf_svc::hal::task::block_on(async move {
         app.run(move |client| {
+            if stop_btn.is_pressed() && client.emergency_stop().is_err() {
+                log::error!(target: "game", "Failed to run the emergency stop");
+            }
+
             if red_btn.is_pressed() {
                 let result = client.team_press(Team::Red);
                 if result.is_err() {
@@ -53,12 +102,34 @@ fn main() -> Result<()> {
                     log::error!("Failed to register blue team press");
                 }
             }
+
+            let mut levels = levels.lock().unwrap();
+            let [red_held, blue_held] = &mut *levels;
+            for (team, button, was_held) in
+                [(Team::Red, &red_btn, red_held), (Team::Blue, &blue_btn, blue_held)]
+            {
+                let now_held = button.is_active();
+                if now_held != *was_held {
+                    *was_held = now_held;
+                    if client.team_held(team, now_held).is_err() {
+                        log::error!("Failed to register {team:?} team hold");
+                    }
+                }
+            }
+
+            let both_held = red_btn.is_active() && blue_btn.is_active();
+            let combo_fired = combo.lock().unwrap().update(both_held, Instant::now());
+            if combo_fired && client.admin_combo().is_err() {
+                log::error!("Failed to run the button combo");
+            }
         }).await;
     });
 
     Ok(())
 }
 
-fn register_routes(server: &mut HttpServer) {
-    load_svelte(server);
+fn register_routes(server: &mut HttpServer, role: BoardRole) {
+    api::register(server);
+    ws::register(server);
+    load_svelte(server, role);
 }
@@ -0,0 +1,224 @@
+//! Host-side simulation of the game rules, no ESP32 needed.
+//!
+//! `cargo run --bin sim --no-default-features --features sim --target <host triple>`
+//!
+//! The modules it borrows from the firmware bring their unit tests along, `cargo test` with the
+//! same arguments runs them on the host.
+//!
+//! Drives `GameState` with a scripted sequence of button presses on a synthetic clock and
+//! prints what the device would do, audio goes to a stub sink that just logs the clip.
+
+#[allow(dead_code)]
+#[path = "app/audio.rs"]
+mod audio;
+#[allow(dead_code)]
+#[path = "app/game.rs"]
+mod game;
+#[allow(dead_code)]
+#[path = "hardware/audio_gate.rs"]
+mod audio_gate;
+#[allow(dead_code)]
+#[path = "hardware/deferred.rs"]
+mod deferred;
+#[allow(dead_code)]
+#[path = "infra/http.rs"]
+mod http;
+
+use std::time::{Duration, Instant};
+
+use audio::AudioSink;
+use game::{GameConfig, GameState, PressOutcome, Team};
+
+struct PrintSink;
+
+impl AudioSink for PrintSink {
+    fn play(&self, clip: &'static [u8]) {
+        println!("    ♪ playing {} byte clip", clip.len());
+    }
+
+    fn stop(&self) {
+        println!("    ♪ stopped");
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+enum Step {
+    Press(Team),
+    Wait(Duration),
+}
+
+const RED_CLIP: &[u8] = include_bytes!("../data/zona-vermelha-dominada.sbc");
+const BLUE_CLIP: &[u8] = include_bytes!("../data/zona-azul-dominada.sbc");
+
+fn main() {
+    let script = [
+        Step::Press(Team::Red),
+        Step::Wait(Duration::from_secs(4)),
+        Step::Press(Team::Blue),
+        Step::Wait(Duration::from_secs(3)),
+        Step::Press(Team::Blue),
+        Step::Wait(Duration::from_secs(2)),
+        Step::Press(Team::Red),
+        Step::Wait(Duration::from_secs(8)),
+    ];
+
+    println!("Symmetric game, warning at 80%");
+    run(
+        GameConfig {
+            time_to_win: Duration::from_secs(10),
+            warning_threshold: Some(0.8),
+            ..Default::default()
+        },
+        &script,
+    );
+
+    // Attack/defend: red attacks and earns held time at half the rate
+    println!("Red attacking at half rate");
+    run(
+        GameConfig {
+            time_to_win: Duration::from_secs(10),
+            rate_red: 0.5,
+            rate_blue: 1.5,
+            ..Default::default()
+        },
+        &script,
+    );
+
+    // Nobody goes for the point for the first 5s, that shows up as neutral time
+    println!("Slow start with neutral time tracked");
+    run(
+        GameConfig {
+            time_to_win: Duration::from_secs(10),
+            start_neutral_penalty: true,
+            ..Default::default()
+        },
+        &[
+            Step::Wait(Duration::from_secs(5)),
+            Step::Press(Team::Blue),
+            Step::Wait(Duration::from_secs(12)),
+        ],
+    );
+
+    // Attack/defend with separate targets: red needs 6s, blue 9s. Red gets there first
+    // despite holding the point for less time overall.
+    let split_targets = GameConfig {
+        time_to_win: Duration::from_secs(10),
+        time_to_win_red: Some(Duration::from_secs(6)),
+        time_to_win_blue: Some(Duration::from_secs(9)),
+        ..Default::default()
+    };
+    println!("Separate targets, red wins");
+    run(
+        split_targets,
+        &[
+            Step::Press(Team::Blue),
+            Step::Wait(Duration::from_secs(7)),
+            Step::Press(Team::Red),
+            Step::Wait(Duration::from_secs(8)),
+        ],
+    );
+
+    // Same targets, blue keeps the point long enough for its own
+    println!("Separate targets, blue wins");
+    run(
+        split_targets,
+        &[
+            Step::Press(Team::Red),
+            Step::Wait(Duration::from_secs(5)),
+            Step::Press(Team::Blue),
+            Step::Wait(Duration::from_secs(10)),
+        ],
+    );
+
+    // Called with both teams on exactly the same held time
+    println!("Dead heat");
+    run(
+        GameConfig {
+            time_to_win: Duration::from_secs(10),
+            ..Default::default()
+        },
+        &[
+            Step::Press(Team::Red),
+            Step::Wait(Duration::from_secs(4)),
+            Step::Press(Team::Blue),
+            Step::Wait(Duration::from_secs(4)),
+        ],
+    );
+
+    // A game nobody stopped, with the loop stuck for hours: held time stays capped
+    println!("Clock jumping ahead by hours");
+    stalled(Duration::from_secs(5 * 60 * 60));
+}
+
+fn stalled(gap: Duration) {
+    let mut game = GameState::with_config(GameConfig {
+        time_to_win: Duration::from_secs(10),
+        rate_red: 1000.0,
+        max_duration: None,
+        ..Default::default()
+    });
+    let start = Instant::now();
+    game.start_at(start);
+    game.button_press_at(Team::Red, start);
+    game.tick_at(start + gap);
+    println!("[{:>5.1}s] {:?}", gap.as_secs_f32(), game.scores());
+    println!("[{:>5.1}s] {:?} wins", gap.as_secs_f32(), game.winner());
+    game.stop_at(start + gap);
+}
+
+fn run(config: GameConfig, script: &[Step]) {
+    let sink = PrintSink;
+    let mut game = GameState::with_config(config);
+    let start = Instant::now();
+    let mut now = start;
+    game.start_at(now);
+
+    for step in script {
+        match *step {
+            Step::Press(team) => {
+                let outcome = game.button_press_at(team, now);
+                println!("[{:>5.1}s] {team:?} pressed -> {outcome:?}", (now - start).as_secs_f32());
+                if let PressOutcome::Captured(team) = outcome {
+                    sink.play(match team {
+                        Team::Red => RED_CLIP,
+                        Team::Blue => BLUE_CLIP,
+                    });
+                }
+            }
+            Step::Wait(duration) => {
+                // Tick at the device's cadence so winner detection matches the firmware
+                let end = now + duration;
+                while now < end && game.winner().is_none() {
+                    now += Duration::from_millis(10);
+                    if let Some(team) = game.tick_at(now) {
+                        let at = (now - start).as_secs_f32();
+                        println!("[{at:>5.1}s] {team:?} is close to winning");
+                    }
+                }
+                println!("[{:>5.1}s] {:?}", (now - start).as_secs_f32(), game.scores());
+            }
+        }
+
+        if let Some(winner) = game.winner() {
+            println!("[{:>5.1}s] {winner:?} wins", (now - start).as_secs_f32());
+            game.stop_at(now);
+            print_owner_history(&game);
+            return;
+        }
+    }
+
+    // Out of script before anyone won, decide on held time like the referee would
+    println!("[{:>5.1}s] Called -> {:?}", (now - start).as_secs_f32(), game.decide());
+    game.stop_at(now);
+    print_owner_history(&game);
+}
+
+fn print_owner_history(game: &GameState) {
+    for span in game.owner_history() {
+        let end = span.end.unwrap_or_default();
+        println!("    {:?} held {:?} to {:?}", span.team, span.start, end);
+    }
+}